@@ -56,12 +56,12 @@ export function useConsultationEscrow() {
   );
 
   /**
-   * Get platform fee percentage
+   * Get platform fee in basis points
    */
-  const getPlatformFeePercent = useCallback(async (): Promise<number> => {
+  const getPlatformFeeBps = useCallback(async (): Promise<number> => {
     try {
       const contract = getContract();
-      const { result, output } = await contract.query.getPlatformFeePercent(
+      const { result, output } = await contract.query.getPlatformFeeBps(
         selectedAccount?.address || '',
         { gasLimit: { refTime: 1000000000, proofSize: 1000000 }as any }
       );
@@ -69,10 +69,10 @@ export function useConsultationEscrow() {
       if (result.isOk && output) {
         return parseInt(output.toString());
       }
-      return 3; // Default 3%
+      return 300; // Default 3%
     } catch (err: any) {
       console.error('Failed to get platform fee:', parseContractError(err));
-      return 3;
+      return 300;
     }
   }, [api, selectedAccount, getContract]);
 
@@ -399,7 +399,7 @@ export function useConsultationEscrow() {
     
     // Read operations
     getConsultation,
-    getPlatformFeePercent,
+    getPlatformFeeBps,
     
     // Write operations
     bookConsultation,
@@ -18,6 +18,7 @@ export interface DoctorProfile {
   completed_consultations: number;
   cancelled_consultations: number;
   no_show_count: number;
+  bonded_amount: bigint;
 }
 
 export interface PatientProfile {
@@ -41,6 +42,7 @@ export interface DoctorStats {
   rating: number;
   completion_rate: number;
   verified: boolean;
+  bonded_amount: bigint;
 }
 
 export interface Consultation {
@@ -64,6 +66,8 @@ export const ConsultationStatus = {
   Released: 'Released',
   Cancelled: 'Cancelled',
   NoShow: 'NoShow',
+  PatientNoShow: 'PatientNoShow',
+  Expired: 'Expired',
 } as const;
 
 export type ConsultationStatus = typeof ConsultationStatus[keyof typeof ConsultationStatus];
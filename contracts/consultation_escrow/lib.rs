@@ -2,17 +2,150 @@
 
 #[ink::contract]
 mod consultation_escrow {
+    use ink::env::call::{build_call, ExecutionInput, Selector};
+    use ink::env::DefaultEnvironment;
     use ink::prelude::string::String;
+    use ink::prelude::vec::Vec;
+    use ink::storage::traits::StorageKey;
     use ink::storage::Mapping;
 
+    const HOUR_MS: u64 = 60 * 60 * 1000;
+    const MAX_POLICY_TIERS: usize = 5;
+    const MAX_NOTICE_HOURS: u32 = 14 * 24;
+    const MAX_RESCHEDULES: u8 = 2;
+    const CHECK_IN_OPENS_BEFORE_MS: u64 = 30 * 60 * 1000;
+    const MAX_BATCH_SIZE: usize = 50;
+    const BPS_DENOMINATOR: u16 = 10_000;
+    const MAX_FEE_BENEFICIARIES: usize = 5;
+    const MAX_PACKAGE_SESSIONS: u32 = 52;
+    const MAX_PREPAID_PERIODS: u32 = 12;
+    const MAX_FOLLOW_UPS: usize = 5;
+    const MAX_CHAIN_LENGTH: usize = 50;
+    const MAX_CONFERENCE_DOCTORS: usize = 5;
+    const CONFERENCE_CONFIRMATION_WINDOW: u64 = 48 * HOUR_MS;
+    const MAX_QUEUE_LENGTH: usize = 100;
+    const MAX_WAITLIST_LENGTH: usize = 50;
+    const MAX_BOOKING_HORIZON: u64 = 180 * DAY_MS;
+    const DEFAULT_SESSION_DURATION: u64 = 30 * 60 * 1000;
+    const MAX_SESSION_DURATION: u64 = 8 * HOUR_MS;
+    const MAX_PAGE_SIZE: u32 = 50;
+    const DAY_MS: u64 = 24 * HOUR_MS;
+    const DISPUTE_WINDOW_MS: u64 = 24 * HOUR_MS;
+    const MAX_SUMMARY_DAYS: u32 = 90;
+    const DEFAULT_BOND_COOLDOWN: u64 = 7 * DAY_MS;
+
+    // Registry messages return `Result<(), health_registry::Error>`; its unit-only
+    // error enum encodes as a single variant index.
+    type RegistryResult = core::result::Result<(), u8>;
+
     #[ink(storage)]
     pub struct ConsultationEscrow {
         next_id: u64,
         consultations: Mapping<u64, Consultation>,
-        platform_fee_percent: u8,
+        platform_fee_bps: u16,
         platform_wallet: AccountId,
         owner: AccountId,
         health_registry_address: AccountId,
+        doctor_bonds: Mapping<AccountId, Balance>,
+        doctor_cancellation_penalty: Balance,
+        cancellation_policies: Mapping<AccountId, Vec<RefundTier>>,
+        min_refund_percent: u8,
+        reschedule_proposals: Mapping<u64, RescheduleProposal>,
+        no_show_grace_period: u64,
+        patient_no_show_doctor_percent: u8,
+        sla_config: SlaConfig,
+        doctor_classes: Mapping<AccountId, DoctorClass>,
+        specialty_fee_bps: Mapping<u16, u16>,
+        tier_fee_bps: Mapping<u8, u16>,
+        fee_beneficiaries: Vec<FeeBeneficiary>,
+        accrued_fees: Mapping<AccountId, Balance>,
+        subsidy_pool: SubsidyPool,
+        subsidy_limits: SubsidyLimits,
+        subsidy_approvers: Mapping<AccountId, bool>,
+        subsidy_eligible: Mapping<AccountId, bool>,
+        subsidy_usage: Mapping<AccountId, SubsidyUsage>,
+        package_offers: Mapping<(AccountId, u32), Balance>,
+        packages: Mapping<u64, CarePackage>,
+        next_package_id: u64,
+        subscription_plans: Mapping<u64, SubscriptionPlan>,
+        next_plan_id: u64,
+        subscriptions: Mapping<u64, Subscription>,
+        next_subscription_id: u64,
+        follow_up_policies: Mapping<AccountId, FollowUpPolicy>,
+        follow_ups: Mapping<u64, Vec<u64>>,
+        conferences: Mapping<u64, CaseConference>,
+        next_conference_id: u64,
+        async_cases: Mapping<u64, AsyncCase>,
+        next_async_case_id: u64,
+        async_sla: AsyncSla,
+        queue_tickets: Mapping<u64, QueueTicket>,
+        next_ticket_id: u64,
+        specialty_queues: Mapping<u16, Vec<u64>>,
+        on_call_doctors: Mapping<AccountId, bool>,
+        queue_timeout: u64,
+        waitlists: Mapping<AccountId, Vec<WaitlistEntry>>,
+        slot_offers: Mapping<(AccountId, u64), SlotOffer>,
+        slot_offer_window: u64,
+        session_durations: Mapping<AccountId, u64>,
+        overtime_terms: Mapping<AccountId, OvertimeTerms>,
+        /// Per-account consultation ids in booking order, one entry per
+        /// `(account, position)` key so no single storage value keeps growing.
+        patient_consultations: Mapping<(AccountId, u32), u64>,
+        patient_consultation_count: Mapping<AccountId, u32>,
+        doctor_consultations: Mapping<(AccountId, u32), u64>,
+        doctor_consultation_count: Mapping<AccountId, u32>,
+        /// Per-doctor count of consultations that are not yet settled.
+        open_consultation_count: Mapping<AccountId, u32>,
+        /// Unsettled consultations per doctor and scheduled time.
+        booked_slots: Mapping<(AccountId, u64), u32>,
+        /// Each doctor's completed consultations in completion order, consumed
+        /// from `release_queue_head` by `release_matured`.
+        release_queue: Mapping<(AccountId, u32), u64>,
+        release_queue_count: Mapping<AccountId, u32>,
+        release_queue_head: Mapping<AccountId, u32>,
+        /// Per-doctor count of unsettled case conferences and claimed async cases.
+        open_case_work: Mapping<AccountId, u32>,
+        doctor_totals: Mapping<AccountId, EarningsTotals>,
+        platform_totals: EarningsTotals,
+        doctor_daily_earnings: Mapping<(AccountId, u64), DailyEarnings>,
+        platform_daily_earnings: Mapping<u64, DailyEarnings>,
+        receipts: Mapping<u64, Receipt>,
+        next_receipt_number: u64,
+        /// Per-account receipt ids in issue order, indexed like the consultations.
+        patient_receipts: Mapping<(AccountId, u32), u64>,
+        patient_receipt_count: Mapping<AccountId, u32>,
+        doctor_receipts: Mapping<(AccountId, u32), u64>,
+        doctor_receipt_count: Mapping<AccountId, u32>,
+        bond_slash_amount: Balance,
+        bond_withdrawal_cooldown: u64,
+        bond_withdrawal_requests: Mapping<AccountId, BondWithdrawalRequest>,
+        patient_reliability: Mapping<AccountId, PatientReliability>,
+        deposit_requirements: Mapping<AccountId, DepositRequirement>,
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct PatientReliability {
+        pub completed: u32,
+        pub late_cancellations: u32,
+        pub no_shows: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct DepositRequirement {
+        pub no_show_threshold: u32,
+        pub deposit: Balance,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct BondWithdrawalRequest {
+        pub amount: Balance,
+        pub requested_at: u64,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq)]
@@ -21,6 +154,7 @@ mod consultation_escrow {
     pub struct Consultation {
         pub id: u64,
         pub patient: AccountId,
+        pub payer: AccountId,
         pub doctor: AccountId,
         pub amount: Balance,
         pub status: ConsultationStatus,
@@ -28,6 +162,361 @@ mod consultation_escrow {
         pub created_at: u64,
         pub completed_at: Option<u64>,
         pub notes_ipfs_hash: Option<String>,
+        pub cancellation_policy: Vec<RefundTier>,
+        pub reschedule_count: u8,
+        pub patient_checked_in_at: Option<u64>,
+        pub doctor_checked_in_at: Option<u64>,
+        pub attestation: SessionAttestation,
+        pub fee_bps: u16,
+        pub subsidy_amount: Balance,
+        pub package_id: Option<u64>,
+        pub parent_id: Option<u64>,
+        pub duration: u64,
+        pub started_at: Option<u64>,
+        pub ended_at: Option<u64>,
+        pub overtime_terms: Option<OvertimeTerms>,
+        pub overtime_allowance: Balance,
+        pub overtime_charged: Balance,
+        pub reliability_deposit: Balance,
+        pub waitlist_deposit: Balance,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct Receipt {
+        pub receipt_number: u64,
+        pub consultation_id: u64,
+        pub payer: AccountId,
+        pub patient: AccountId,
+        pub doctor: AccountId,
+        pub gross: Balance,
+        pub patient_paid: Balance,  // Out of pocket: excludes subsidies, deposits and third-party payments
+        pub fee: Balance,
+        pub net: Balance,
+        pub asset: PaymentAsset,
+        pub specialty_code: Option<u16>,
+        pub booked_at: u64,
+        pub scheduled_time: u64,
+        pub completed_at: Option<u64>,
+        pub settled_at: u64,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum PaymentAsset {
+        Native,
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct EarningsTotals {
+        pub locked: Balance,
+        pub released: Balance,
+        pub refunded: Balance,
+        pub fees: Balance,
+        /// Keeper rewards withheld from doctors' escrowed shares. Rewards taken
+        /// from a bond are not escrowed funds and, like slashes, aren't counted.
+        pub keeper_rewards: Balance,
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct DailyEarnings {
+        pub released: Balance,
+        pub fees: Balance,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct OvertimeTerms {
+        pub increment: u64,
+        pub rate_per_increment: Balance,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct CaseConference {
+        pub id: u64,
+        pub patient: AccountId,
+        pub payer: AccountId,
+        pub amount: Balance,
+        pub participants: Vec<ConferenceParticipant>,
+        pub status: ConferenceStatus,
+        pub scheduled_time: u64,
+        pub created_at: u64,
+        pub completed_at: Option<u64>,
+        pub confirm_by: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct ConferenceParticipant {
+        pub doctor: AccountId,
+        pub share_bps: u16,
+        pub fee_bps: u16,
+        pub confirmed: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum ConferenceStatus {
+        Pending,
+        Completed,
+        Released,
+        Cancelled,
+        Lapsed,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct AsyncCase {
+        pub id: u64,
+        pub patient: AccountId,
+        pub specialty_code: u16,
+        pub case_ipfs_hash: String,
+        pub amount: Balance,
+        pub status: AsyncCaseStatus,
+        pub doctor: Option<AccountId>,
+        pub fee_bps: u16,
+        pub submitted_at: u64,
+        pub claimed_at: Option<u64>,
+        pub responded_at: Option<u64>,
+        pub response_ipfs_hash: Option<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum AsyncCaseStatus {
+        Open,      // Waiting for a doctor to claim it
+        Claimed,   // Doctor is working on a response
+        Answered,  // Response delivered, dispute window running
+        Disputed,  // Patient disputed the response, awaiting owner resolution
+        Released,
+        Refunded,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct AsyncSla {
+        pub claim_timeout: u64,  // ms after submission
+        pub response_time: u64,  // ms after claiming
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct QueueTicket {
+        pub id: u64,
+        pub patient: AccountId,
+        pub specialty_code: u16,
+        pub amount: Balance,
+        pub joined_at: u64,
+        pub status: TicketStatus,
+        pub consultation_id: Option<u64>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum TicketStatus {
+        Waiting,
+        Assigned,
+        Left,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct WaitlistEntry {
+        pub patient: AccountId,
+        pub deposit: Balance,
+        pub joined_at: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct SlotOffer {
+        pub patient: AccountId,
+        pub deposit: Balance,
+        pub expires_at: u64,
+    }
+
+    /// Mirror of `health_registry::TimeSlot`, used to decode availability.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct RegistryTimeSlot {
+        pub start_time: u64,
+        pub end_time: u64,
+        pub is_booked: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct FollowUpPolicy {
+        pub window: u64,          // ms after the parent completes
+        pub discount_percent: u8, // 100 makes follow-ups free
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct SessionAttestation {
+        pub start_by_patient: bool,
+        pub start_by_doctor: bool,
+        pub end_by_patient: bool,
+        pub end_by_doctor: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct DoctorClass {
+        pub specialty_code: u16,
+        pub tier: u8,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct FeeBeneficiary {
+        pub account: AccountId,
+        pub weight: u32,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct CarePackage {
+        pub id: u64,
+        pub patient: AccountId,
+        pub doctor: AccountId,
+        pub price: Balance,
+        pub sessions_total: u32,
+        pub sessions_used: u32,
+        pub value_used: Balance,
+        pub status: PackageStatus,
+        pub created_at: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum PackageStatus {
+        Active,
+        Exhausted,
+        Cancelled,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct SubscriptionPlan {
+        pub id: u64,
+        pub doctor: AccountId,
+        pub price_per_period: Balance,
+        pub period: u64,
+        pub terms_ipfs_hash: String,
+        pub active: bool,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct Subscription {
+        pub id: u64,
+        pub plan_id: u64,
+        pub patient: AccountId,
+        pub doctor: AccountId,
+        pub price_per_period: Balance,
+        pub period: u64,
+        pub fee_bps: u16,
+        pub started_at: u64,
+        pub prepaid_periods: u32,
+        pub released_periods: u32,
+        pub status: SubscriptionStatus,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum SubscriptionStatus {
+        Active,
+        Cancelled,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Default)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct SubsidyPool {
+        pub balance: Balance,
+        pub total_donated: Balance,
+        pub total_disbursed: Balance,
+        pub total_returned: Balance,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct SubsidyLimits {
+        pub max_per_consultation: Balance,
+        pub max_per_patient_per_period: Balance,
+        pub period: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct SubsidyUsage {
+        pub period_index: u64,
+        pub used: Balance,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct SlaConfig {
+        pub start_deadline: u64,       // ms after scheduled_time to start
+        pub completion_deadline: u64,  // ms after scheduled_time to complete
+        pub in_progress_doctor_percent: u8,
+        pub keeper_reward: Balance,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum SessionPhase {
+        Start,
+        End,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct RefundTier {
+        pub hours_before: u32,
+        pub refund_percent: u8,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct RescheduleProposal {
+        pub proposed_by: AccountId,
+        pub new_time: u64,
+        pub proposed_at: u64,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq)]
@@ -41,7 +530,9 @@ mod consultation_escrow {
         Refunded,     // Refunded to patient
         Released,     // Payment released to doctor
         Cancelled,
-        NoShow,
+        NoShow,         // Doctor did not show up
+        PatientNoShow,  // Patient did not show up
+        Expired,        // Settled after missing an SLA deadline
     }
 
     #[ink(event)]
@@ -52,6 +543,7 @@ mod consultation_escrow {
         patient: AccountId,
         #[ink(topic)]
         doctor: AccountId,
+        payer: AccountId,
         amount: Balance,
     }
 
@@ -61,6 +553,25 @@ mod consultation_escrow {
         consultation_id: u64,
     }
 
+    #[ink(event)]
+    pub struct ReceiptIssued {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        patient: AccountId,
+        receipt_number: u64,
+        receipt_hash: [u8; 32],
+    }
+
+    #[ink(event)]
+    pub struct OvertimeCharged {
+        #[ink(topic)]
+        consultation_id: u64,
+        increments: u64,
+        charged: Balance,
+        refunded: Balance,
+    }
+
     #[ink(event)]
     pub struct PaymentReleased {
         #[ink(topic)]
@@ -71,13 +582,21 @@ mod consultation_escrow {
     }
 
     #[ink(event)]
-    pub struct ConsultationDisputed {
+    pub struct FeesWithdrawn {
         #[ink(topic)]
-        consultation_id: u64,
+        beneficiary: AccountId,
+        amount: Balance,
     }
 
     #[ink(event)]
-    pub struct ConsultationRefunded {
+    pub struct SubsidyDonated {
+        #[ink(topic)]
+        donor: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SubsidyGranted {
         #[ink(topic)]
         consultation_id: u64,
         #[ink(topic)]
@@ -86,44 +605,437 @@ mod consultation_escrow {
     }
 
     #[ink(event)]
-    pub struct ConsultationCancelled {
+    pub struct SubsidyEligibilityUpdated {
         #[ink(topic)]
-        consultation_id: u64,
-        cancelled_by: AccountId,
-        refund_amount: Balance,
+        patient: AccountId,
+        eligible: bool,
+        updated_by: AccountId,
     }
 
     #[ink(event)]
-    pub struct NoShowReported {
+    pub struct PackagePurchased {
         #[ink(topic)]
-        consultation_id: u64,
+        package_id: u64,
+        #[ink(topic)]
+        patient: AccountId,
         #[ink(topic)]
         doctor: AccountId,
+        sessions: u32,
+        price: Balance,
     }
 
-    #[derive(Debug, PartialEq, Eq)]
-    #[ink::scale_derive(Encode, Decode, TypeInfo)]
-    pub enum Error {
-        Unauthorized,
-        ConsultationNotFound,
-        InvalidStatus,
-        InsufficientPayment,
-        TransferFailed,
-        DisputeWindowExpired,
-        TooEarlyToRelease,
-        OverflowError,
-        DoctorNotVerified,
-        SlotNotAvailable,
-        CancellationNotAllowed,
+    #[ink(event)]
+    pub struct PackageCancelled {
+        #[ink(topic)]
+        package_id: u64,
+        refund_amount: Balance,
     }
 
-    pub type Result<T> = core::result::Result<T, Error>;
+    #[ink(event)]
+    pub struct SubscriptionStarted {
+        #[ink(topic)]
+        subscription_id: u64,
+        #[ink(topic)]
+        patient: AccountId,
+        #[ink(topic)]
+        doctor: AccountId,
+        plan_id: u64,
+    }
 
-    impl Default for ConsultationEscrow {
+    #[ink(event)]
+    pub struct SubscriptionRenewed {
+        #[ink(topic)]
+        subscription_id: u64,
+        prepaid_periods: u32,
+    }
+
+    #[ink(event)]
+    pub struct SubscriptionPeriodsReleased {
+        #[ink(topic)]
+        subscription_id: u64,
+        periods: u32,
+        doctor_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SubscriptionCancelled {
+        #[ink(topic)]
+        subscription_id: u64,
+        cancelled_by: AccountId,
+        refund_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct FollowUpBooked {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        parent_id: u64,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ConferenceBooked {
+        #[ink(topic)]
+        conference_id: u64,
+        #[ink(topic)]
+        patient: AccountId,
+        doctors_count: u32,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ConferenceCompletionConfirmed {
+        #[ink(topic)]
+        conference_id: u64,
+        #[ink(topic)]
+        doctor: AccountId,
+        all_confirmed: bool,
+    }
+
+    #[ink(event)]
+    pub struct ConferencePaymentReleased {
+        #[ink(topic)]
+        conference_id: u64,
+        total_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ConferenceLapsed {
+        #[ink(topic)]
+        conference_id: u64,
+        paid_to_doctors: Balance,
+        refunded: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ConferenceCancelled {
+        #[ink(topic)]
+        conference_id: u64,
+        cancelled_by: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct AsyncCaseSubmitted {
+        #[ink(topic)]
+        case_id: u64,
+        #[ink(topic)]
+        specialty_code: u16,
+        patient: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct AsyncCaseClaimed {
+        #[ink(topic)]
+        case_id: u64,
+        #[ink(topic)]
+        doctor: AccountId,
+        respond_by: u64,
+    }
+
+    #[ink(event)]
+    pub struct AsyncCaseAnswered {
+        #[ink(topic)]
+        case_id: u64,
+        #[ink(topic)]
+        doctor: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct AsyncCaseDisputed {
+        #[ink(topic)]
+        case_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct AsyncCaseSettled {
+        #[ink(topic)]
+        case_id: u64,
+        status: AsyncCaseStatus,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct QueueJoined {
+        #[ink(topic)]
+        ticket_id: u64,
+        #[ink(topic)]
+        specialty_code: u16,
+        patient: AccountId,
+        position: u32,
+    }
+
+    #[ink(event)]
+    pub struct QueueCasePulled {
+        #[ink(topic)]
+        ticket_id: u64,
+        #[ink(topic)]
+        doctor: AccountId,
+        consultation_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct QueueLeft {
+        #[ink(topic)]
+        ticket_id: u64,
+        refund_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct WaitlistJoined {
+        #[ink(topic)]
+        doctor: AccountId,
+        #[ink(topic)]
+        patient: AccountId,
+        deposit: Balance,
+        position: u32,
+    }
+
+    #[ink(event)]
+    pub struct SlotOffered {
+        #[ink(topic)]
+        doctor: AccountId,
+        #[ink(topic)]
+        patient: AccountId,
+        slot_time: u64,
+        expires_at: u64,
+    }
+
+    #[ink(event)]
+    pub struct ConsultationDisputed {
+        #[ink(topic)]
+        consultation_id: u64,
+    }
+
+    #[ink(event)]
+    pub struct ConsultationRefunded {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        payer: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ConsultationCancelled {
+        #[ink(topic)]
+        consultation_id: u64,
+        cancelled_by: AccountId,
+        refund_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BondDeposited {
+        #[ink(topic)]
+        doctor: AccountId,
+        amount: Balance,
+        total_bond: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BondWithdrawn {
+        #[ink(topic)]
+        doctor: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BondWithdrawalRequested {
+        #[ink(topic)]
+        doctor: AccountId,
+        amount: Balance,
+        available_at: u64,
+    }
+
+    #[ink(event)]
+    pub struct ReliabilityDepositSettled {
+        #[ink(topic)]
+        consultation_id: u64,
+        recipient: AccountId,
+        amount: Balance,
+        forfeited: bool,
+    }
+
+    #[ink(event)]
+    pub struct BondSlashed {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        doctor: AccountId,
+        amount: Balance,
+        remaining_bond: Balance,
+    }
+
+    #[ink(event)]
+    pub struct RegistryUpdateFailed {
+        #[ink(topic)]
+        doctor: AccountId,
+        selector: [u8; 4],
+    }
+
+    #[ink(event)]
+    pub struct BondForfeited {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        doctor: AccountId,
+        patient: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct CancellationPolicyUpdated {
+        #[ink(topic)]
+        doctor: AccountId,
+        tiers_count: u32,
+    }
+
+    #[ink(event)]
+    pub struct RescheduleProposed {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        proposed_by: AccountId,
+        new_time: u64,
+    }
+
+    #[ink(event)]
+    pub struct ConsultationRescheduled {
+        #[ink(topic)]
+        consultation_id: u64,
+        old_time: u64,
+        new_time: u64,
+    }
+
+    #[ink(event)]
+    pub struct NoShowReported {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        doctor: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct CheckedIn {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        account: AccountId,
+        checked_in_at: u64,
+    }
+
+    #[ink(event)]
+    pub struct SessionAttested {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        account: AccountId,
+        phase: SessionPhase,
+        cosigned: bool,
+    }
+
+    #[ink(event)]
+    pub struct ConsultationExpired {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        keeper: AccountId,
+        keeper_reward: Balance,
+        doctor_amount: Balance,
+        refund_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct PatientNoShowReported {
+        #[ink(topic)]
+        consultation_id: u64,
+        #[ink(topic)]
+        patient: AccountId,
+        doctor_amount: Balance,
+        refund_amount: Balance,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Error {
+        Unauthorized,
+        ConsultationNotFound,
+        InvalidStatus,
+        InsufficientPayment,
+        TransferFailed,
+        DisputeWindowExpired,
+        TooEarlyToRelease,
+        Overflow,
+        DoctorNotVerified,
+        SlotNotAvailable,
+        CancellationNotAllowed,
+        InsufficientBond,
+        InvalidCancellationPolicy,
+        InvalidScheduleTime,
+        NoRescheduleProposal,
+        RescheduleLimitReached,
+        CheckInClosed,
+        AlreadyCheckedIn,
+        NoShowClaimRejected,
+        InvalidPercentage,
+        DeadlineNotReached,
+        InvalidSlaConfig,
+        BatchTooLarge,
+        InvalidFee,
+        InvalidBeneficiaries,
+        NothingToWithdraw,
+        NotEligibleForSubsidy,
+        InvalidSubsidyLimits,
+        PackageNotFound,
+        PackageNotActive,
+        NoCreditsRemaining,
+        InvalidPackage,
+        IncorrectPayment,
+        PlanNotFound,
+        PlanInactive,
+        InvalidPlan,
+        SubscriptionNotFound,
+        SubscriptionNotActive,
+        TooManyPrepaidPeriods,
+        NothingToRelease,
+        FollowUpWindowExpired,
+        TooManyFollowUps,
+        ConferenceNotFound,
+        InvalidParticipants,
+        AlreadyConfirmed,
+        AsyncCaseNotFound,
+        SpecialtyMismatch,
+        ResponseDeadlinePassed,
+        TicketNotFound,
+        QueueEmpty,
+        NoAssignableTicket,
+        QueueFull,
+        NotOnCall,
+        AlreadyWaitlisted,
+        NotWaitlisted,
+        WaitlistFull,
+        NoSlotOffer,
+        OfferStillActive,
+        InvalidSessionDuration,
+        InvalidOvertimeTerms,
+        OvertimeNotOffered,
+        WithdrawalNotRequested,
+        CooldownActive,
+        OpenConsultations,
+        InvalidDepositRequirement,
+        DepositRequired,
+        SubscriptionLapsed,
+        FreeSlotsAvailable,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl Default for ConsultationEscrow {
         fn default() -> Self {
             Self::new(
                 AccountId::from([0x0; 32]),
-                3,
+                300,
                 AccountId::from([0x0; 32])
             )
         }
@@ -133,16 +1045,95 @@ mod consultation_escrow {
         #[ink(constructor)]
         pub fn new(
             platform_wallet: AccountId, 
-            platform_fee_percent: u8, 
+            platform_fee_bps: u16,
             health_registry_address: AccountId,
         ) -> Self {
             Self {
                 next_id: 1,
                 consultations: Mapping::default(),
-                platform_fee_percent,
+                platform_fee_bps,
                 platform_wallet,
                 owner: Self::env().caller(),
                 health_registry_address,
+                doctor_bonds: Mapping::default(),
+                doctor_cancellation_penalty: 0,
+                cancellation_policies: Mapping::default(),
+                min_refund_percent: 0,
+                reschedule_proposals: Mapping::default(),
+                no_show_grace_period: 15 * 60 * 1000,
+                patient_no_show_doctor_percent: 50,
+                sla_config: SlaConfig {
+                    start_deadline: 2 * HOUR_MS,
+                    completion_deadline: 24 * HOUR_MS,
+                    in_progress_doctor_percent: 50,
+                    keeper_reward: 0,
+                },
+                doctor_classes: Mapping::default(),
+                specialty_fee_bps: Mapping::default(),
+                tier_fee_bps: Mapping::default(),
+                fee_beneficiaries: Vec::new(),
+                accrued_fees: Mapping::default(),
+                subsidy_pool: SubsidyPool::default(),
+                subsidy_limits: SubsidyLimits {
+                    max_per_consultation: 0,
+                    max_per_patient_per_period: 0,
+                    period: 30 * 24 * HOUR_MS,
+                },
+                subsidy_approvers: Mapping::default(),
+                subsidy_eligible: Mapping::default(),
+                subsidy_usage: Mapping::default(),
+                package_offers: Mapping::default(),
+                packages: Mapping::default(),
+                next_package_id: 1,
+                subscription_plans: Mapping::default(),
+                next_plan_id: 1,
+                subscriptions: Mapping::default(),
+                next_subscription_id: 1,
+                follow_up_policies: Mapping::default(),
+                follow_ups: Mapping::default(),
+                conferences: Mapping::default(),
+                next_conference_id: 1,
+                async_cases: Mapping::default(),
+                next_async_case_id: 1,
+                async_sla: AsyncSla {
+                    claim_timeout: 24 * HOUR_MS,
+                    response_time: 48 * HOUR_MS,
+                },
+                queue_tickets: Mapping::default(),
+                next_ticket_id: 1,
+                specialty_queues: Mapping::default(),
+                on_call_doctors: Mapping::default(),
+                queue_timeout: 30 * 60 * 1000,
+                waitlists: Mapping::default(),
+                slot_offers: Mapping::default(),
+                slot_offer_window: 2 * HOUR_MS,
+                session_durations: Mapping::default(),
+                overtime_terms: Mapping::default(),
+                patient_consultations: Mapping::default(),
+                patient_consultation_count: Mapping::default(),
+                doctor_consultations: Mapping::default(),
+                doctor_consultation_count: Mapping::default(),
+                open_consultation_count: Mapping::default(),
+                booked_slots: Mapping::default(),
+                release_queue: Mapping::default(),
+                release_queue_count: Mapping::default(),
+                release_queue_head: Mapping::default(),
+                open_case_work: Mapping::default(),
+                doctor_totals: Mapping::default(),
+                platform_totals: EarningsTotals::default(),
+                doctor_daily_earnings: Mapping::default(),
+                platform_daily_earnings: Mapping::default(),
+                receipts: Mapping::default(),
+                next_receipt_number: 1,
+                patient_receipts: Mapping::default(),
+                patient_receipt_count: Mapping::default(),
+                doctor_receipts: Mapping::default(),
+                doctor_receipt_count: Mapping::default(),
+                bond_slash_amount: 0,
+                bond_withdrawal_cooldown: DEFAULT_BOND_COOLDOWN,
+                bond_withdrawal_requests: Mapping::default(),
+                patient_reliability: Mapping::default(),
+                deposit_requirements: Mapping::default(),
             }
         }
 
@@ -153,36 +1144,202 @@ mod consultation_escrow {
             scheduled_time: u64,
         ) -> Result<u64> {
             let caller = self.env().caller();
+            self.book_consultation_for(caller, doctor, scheduled_time)
+        }
+
+        #[ink(message, payable)]
+        pub fn book_consultation_for(
+            &mut self,
+            patient: AccountId,
+            doctor: AccountId,
+            scheduled_time: u64,
+        ) -> Result<u64> {
+            let payer = self.env().caller();
             let amount = self.env().transferred_value();
 
             if amount == 0 {
                 return Err(Error::InsufficientPayment);
             }
 
-            let consultation_id = self.next_id;
-            
-            let consultation = Consultation {
-                id: consultation_id,
-                patient: caller,
-                doctor,
+            // The reliability deposit is held apart, so there must be a fee left.
+            if amount <= self.get_required_deposit(doctor, patient) {
+                return Err(Error::DepositRequired);
+            }
+
+            self.create_consultation(payer, patient, doctor, scheduled_time, amount, amount)
+        }
+
+        #[ink(message, payable)]
+        pub fn book_subsidized_consultation(
+            &mut self,
+            doctor: AccountId,
+            scheduled_time: u64,
+            requested_subsidy: Balance,
+        ) -> Result<u64> {
+            let patient = self.env().caller();
+            let paid = self.env().transferred_value();
+
+            if !self.is_subsidy_eligible(patient) {
+                return Err(Error::NotEligibleForSubsidy);
+            }
+
+            let period_index = self.current_subsidy_period();
+            let used = self.subsidy_usage
+                .get(patient)
+                .filter(|usage| usage.period_index == period_index)
+                .map(|usage| usage.used)
+                .unwrap_or(0);
+            let allowance = self.subsidy_limits.max_per_patient_per_period.saturating_sub(used);
+
+            let subsidy = requested_subsidy
+                .min(self.subsidy_limits.max_per_consultation)
+                .min(allowance)
+                .min(self.subsidy_pool.balance);
+
+            let amount = paid.checked_add(subsidy).ok_or(Error::Overflow)?;
+            if amount == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+            if amount <= self.get_required_deposit(doctor, patient) {
+                return Err(Error::DepositRequired);
+            }
+
+            let consultation_id =
+                self.create_consultation(patient, patient, doctor, scheduled_time, amount, paid)?;
+
+            if subsidy > 0 {
+                let mut consultation = self.consultations
+                    .get(consultation_id)
+                    .ok_or(Error::ConsultationNotFound)?;
+                consultation.subsidy_amount = subsidy;
+                self.consultations.insert(consultation_id, &consultation);
+
+                self.subsidy_pool.balance = self.subsidy_pool.balance
+                    .checked_sub(subsidy)
+                    .ok_or(Error::Overflow)?;
+                self.subsidy_pool.total_disbursed = self.subsidy_pool.total_disbursed
+                    .checked_add(subsidy)
+                    .ok_or(Error::Overflow)?;
+                self.subsidy_usage.insert(patient, &SubsidyUsage {
+                    period_index,
+                    used: used.checked_add(subsidy).ok_or(Error::Overflow)?,
+                });
+
+                self.env().emit_event(SubsidyGranted {
+                    consultation_id,
+                    patient,
+                    amount: subsidy,
+                });
+            }
+
+            Ok(consultation_id)
+        }
+
+        /// Escrows a new booking. `amount` is everything put towards it and `paid`
+        /// the part of it the payer brought in cash for this booking, as opposed to
+        /// package credit or subsidies.
+        fn create_consultation(
+            &mut self,
+            payer: AccountId,
+            patient: AccountId,
+            doctor: AccountId,
+            scheduled_time: u64,
+            amount: Balance,
+            paid: Balance,
+        ) -> Result<u64> {
+            let current_time = self.env().block_timestamp();
+            if scheduled_time < current_time
+                || scheduled_time > current_time.saturating_add(MAX_BOOKING_HORIZON)
+            {
+                return Err(Error::InvalidScheduleTime);
+            }
+
+            // Patients over the doctor's no-show threshold pay the deposit out of
+            // their cash; it is held apart from the escrowed fee.
+            let reliability_deposit = self.get_required_deposit(doctor, patient);
+            if paid < reliability_deposit {
+                return Err(Error::DepositRequired);
+            }
+            let mut amount = amount
+                .checked_sub(reliability_deposit)
+                .ok_or(Error::DepositRequired)?;
+
+            // A slot offered to a waitlisted patient stays reserved for them until
+            // the offer expires; their deposit counts towards the booking.
+            let mut waitlist_deposit: Balance = 0;
+            if let Some(offer) = self.slot_offers.get((doctor, scheduled_time)) {
+                if offer.patient == patient {
+                    amount = amount.checked_add(offer.deposit).ok_or(Error::Overflow)?;
+                    waitlist_deposit = offer.deposit;
+                    self.slot_offers.remove((doctor, scheduled_time));
+                } else if self.env().block_timestamp() <= offer.expires_at {
+                    return Err(Error::SlotNotAvailable);
+                } else {
+                    self.drop_lapsed_offer(doctor, scheduled_time, &offer)?;
+                }
+            }
+
+            let consultation_id = self.next_id;
+            
+            let consultation = Consultation {
+                id: consultation_id,
+                patient,
+                payer,
+                doctor,
                 amount,
                 status: ConsultationStatus::Pending,
                 scheduled_time,
                 created_at: self.env().block_timestamp(),
                 completed_at: None,
                 notes_ipfs_hash: None,
+                cancellation_policy: self.get_cancellation_policy(doctor),
+                reschedule_count: 0,
+                patient_checked_in_at: None,
+                doctor_checked_in_at: None,
+                attestation: SessionAttestation::default(),
+                fee_bps: self.get_fee_bps_for(doctor),
+                subsidy_amount: 0,
+                package_id: None,
+                parent_id: None,
+                duration: self.get_session_duration(doctor),
+                started_at: None,
+                ended_at: None,
+                overtime_terms: self.overtime_terms.get(doctor),
+                overtime_allowance: 0,
+                overtime_charged: 0,
+                reliability_deposit,
+                waitlist_deposit,
             };
 
             self.consultations.insert(consultation_id, &consultation);
+
+            push_indexed(
+                &mut self.patient_consultations,
+                &mut self.patient_consultation_count,
+                patient,
+                consultation_id,
+            )?;
+            push_indexed(
+                &mut self.doctor_consultations,
+                &mut self.doctor_consultation_count,
+                doctor,
+                consultation_id,
+            )?;
+
+            let open_count = self.open_consultation_count.get(doctor).unwrap_or(0);
+            self.open_consultation_count.insert(doctor, &open_count.saturating_add(1));
+            self.book_slot(doctor, scheduled_time);
+            self.record_locked(Some(doctor), amount);
             
             self.next_id = self.next_id
                 .checked_add(1)
-                .ok_or(Error::OverflowError)?;
+                .ok_or(Error::Overflow)?;
 
             self.env().emit_event(ConsultationBooked {
                 consultation_id,
-                patient: caller,
+                patient,
                 doctor,
+                payer,
                 amount,
             });
 
@@ -204,6 +1361,15 @@ mod consultation_escrow {
                 return Err(Error::InvalidStatus);
             }
 
+            // Starting late doesn't count as showing up: a doctor who missed the
+            // check-in window can't use the start to defeat a no-show claim.
+            let current_time = self.env().block_timestamp();
+            if consultation.doctor_checked_in_at.is_none()
+                && self.in_check_in_window(&consultation, current_time)
+            {
+                consultation.doctor_checked_in_at = Some(current_time);
+            }
+            consultation.started_at = Some(current_time);
             consultation.status = ConsultationStatus::InProgress;
             self.consultations.insert(consultation_id, &consultation);
 
@@ -229,323 +1395,4968 @@ mod consultation_escrow {
                 return Err(Error::InvalidStatus);
             }
 
-            consultation.status = ConsultationStatus::Completed;
-            consultation.completed_at = Some(self.env().block_timestamp());
-            consultation.notes_ipfs_hash = Some(notes_ipfs_hash);
-            self.consultations.insert(consultation_id, &consultation);
+            let current_time = self.env().block_timestamp();
+            self.settle_overtime(&mut consultation, current_time)?;
+            self.settle_reliability_deposit(&mut consultation, false)?;
+            self.update_patient_reliability(consultation.patient, |r| {
+                r.completed = r.completed.saturating_add(1)
+            });
+
+            consultation.status = ConsultationStatus::Completed;
+            consultation.completed_at = Some(current_time);
+            consultation.ended_at = Some(current_time);
+            consultation.notes_ipfs_hash = Some(notes_ipfs_hash);
+            self.consultations.insert(consultation_id, &consultation);
+            push_indexed(
+                &mut self.release_queue,
+                &mut self.release_queue_count,
+                consultation.doctor,
+                consultation_id,
+            )?;
+
+            self.env().emit_event(ConsultationCompleted { consultation_id });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn release_payment(&mut self, consultation_id: u64) -> Result<()> {
+            self.settle_release(consultation_id, true).map(|_| ())
+        }
+
+        #[ink(message)]
+        pub fn release_batch(&mut self, consultation_ids: Vec<u64>) -> Result<Vec<(u64, Result<Balance>)>> {
+            if consultation_ids.len() > MAX_BATCH_SIZE {
+                return Err(Error::BatchTooLarge);
+            }
+
+            let results = consultation_ids
+                .into_iter()
+                .map(|consultation_id| (consultation_id, self.settle_release(consultation_id, false)))
+                .collect();
+
+            Ok(results)
+        }
+
+        /// Releases up to `max` of the doctor's consultations whose dispute window
+        /// has passed, in the order they were completed, and reports what each
+        /// paid the doctor or why it failed.
+        #[ink(message)]
+        pub fn release_matured(&mut self, doctor: AccountId, max: u32) -> Result<Vec<(u64, Result<Balance>)>> {
+            let max = max as usize;
+            if max > MAX_BATCH_SIZE {
+                return Err(Error::BatchTooLarge);
+            }
+
+            // Completions are queued in order, so the first one still inside
+            // its dispute window ends the scan. Entries settled some other way
+            // are dropped as they come up.
+            let current_time = self.env().block_timestamp();
+            let mut head = self.release_queue_head.get(doctor).unwrap_or(0);
+            let end = self.release_queue_count.get(doctor).unwrap_or(0);
+            let mut results = Vec::new();
+            while head < end && results.len() < max {
+                let consultation = self.release_queue
+                    .get((doctor, head))
+                    .and_then(|id| self.consultations.get(id));
+                if let Some(consultation) = consultation {
+                    if consultation.status == ConsultationStatus::Completed {
+                        let matured = consultation.completed_at.is_some_and(|completed_at| {
+                            current_time.saturating_sub(completed_at) >= DISPUTE_WINDOW_MS
+                        });
+                        if !matured {
+                            break;
+                        }
+                        results.push((consultation.id, self.settle_release(consultation.id, false)));
+                    }
+                }
+                self.release_queue.remove((doctor, head));
+                head = head.saturating_add(1);
+            }
+            self.release_queue_head.insert(doctor, &head);
+
+            Ok(results)
+        }
+
+        /// Batched releases pass `owner_override = false`: only a single, explicit
+        /// owner release may cut the dispute window short.
+        fn settle_release(&mut self, consultation_id: u64, owner_override: bool) -> Result<Balance> {
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.status != ConsultationStatus::Completed {
+                return Err(Error::InvalidStatus);
+            }
+
+            let completed_at = consultation.completed_at.ok_or(Error::InvalidStatus)?;
+            let current_time = self.env().block_timestamp();
+            
+            let time_since_completion = current_time
+                .checked_sub(completed_at)
+                .ok_or(Error::TooEarlyToRelease)?;
+
+            if time_since_completion < DISPUTE_WINDOW_MS
+                && !(owner_override && self.env().caller() == self.owner)
+            {
+                return Err(Error::TooEarlyToRelease);
+            }
+
+            let receipt_number = self.next_receipt_number;
+            let next_receipt_number = receipt_number
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            let doctor_amount = self.pay_doctor(
+                consultation.doctor,
+                consultation.amount,
+                consultation.fee_bps,
+            )?;
+            self.record_unlocked(Some(consultation.doctor), consultation.amount);
+
+            consultation.status = ConsultationStatus::Released;
+            self.consultations.insert(consultation_id, &consultation);
+            self.close_consultation(&consultation);
+
+            self.next_receipt_number = next_receipt_number;
+            self.issue_receipt(&consultation, receipt_number, doctor_amount)?;
+
+            self.env().emit_event(PaymentReleased {
+                consultation_id,
+                doctor: consultation.doctor,
+                amount: doctor_amount,
+            });
+
+            Ok(doctor_amount)
+        }
+
+        #[ink(message)]
+        pub fn dispute_consultation(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if consultation.status != ConsultationStatus::Completed {
+                return Err(Error::InvalidStatus);
+            }
+
+            let completed_at = consultation.completed_at.ok_or(Error::InvalidStatus)?;
+            let current_time = self.env().block_timestamp();
+            
+            let time_since_completion = current_time
+                .checked_sub(completed_at)
+                .ok_or(Error::DisputeWindowExpired)?;
+
+            if time_since_completion > DISPUTE_WINDOW_MS {
+                return Err(Error::DisputeWindowExpired);
+            }
+
+            consultation.status = ConsultationStatus::Disputed;
+            self.consultations.insert(consultation_id, &consultation);
+
+            self.env().emit_event(ConsultationDisputed { consultation_id });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn refund_consultation(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            
+            if caller != self.owner {
+                return Err(Error::Unauthorized);
+            }
+
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.status != ConsultationStatus::Disputed {
+                return Err(Error::InvalidStatus);
+            }
+
+            self.refund(&consultation, consultation.amount)?;
+            self.record_unlocked(Some(consultation.doctor), consultation.amount);
+            self.slash_bond(&consultation)?;
+
+            consultation.status = ConsultationStatus::Refunded;
+            self.consultations.insert(consultation_id, &consultation);
+            self.close_consultation(&consultation);
+
+            self.env().emit_event(ConsultationRefunded {
+                consultation_id,
+                payer: consultation.payer,
+                amount: consultation.amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_consultation(&self, consultation_id: u64) -> Option<Consultation> {
+            self.consultations.get(consultation_id)
+        }
+
+        #[ink(message)]
+        pub fn get_patient_consultations(
+            &self,
+            patient: AccountId,
+            status: Option<ConsultationStatus>,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Consultation> {
+            let count = self.patient_consultation_count.get(patient).unwrap_or(0);
+            let ids = indexed_ids(&self.patient_consultations, patient, 0..count);
+            self.page_consultations(ids, status, offset, limit)
+        }
+
+        #[ink(message)]
+        pub fn get_doctor_consultations(
+            &self,
+            doctor: AccountId,
+            status: Option<ConsultationStatus>,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Consultation> {
+            let count = self.doctor_consultation_count.get(doctor).unwrap_or(0);
+            let ids = indexed_ids(&self.doctor_consultations, doctor, 0..count);
+            self.page_consultations(ids, status, offset, limit)
+        }
+
+        #[ink(message)]
+        pub fn get_patient_status_counts(&self, patient: AccountId) -> Vec<(ConsultationStatus, u32)> {
+            let count = self.patient_consultation_count.get(patient).unwrap_or(0);
+            self.count_by_status(indexed_ids(&self.patient_consultations, patient, 0..count))
+        }
+
+        #[ink(message)]
+        pub fn get_doctor_status_counts(&self, doctor: AccountId) -> Vec<(ConsultationStatus, u32)> {
+            let count = self.doctor_consultation_count.get(doctor).unwrap_or(0);
+            self.count_by_status(indexed_ids(&self.doctor_consultations, doctor, 0..count))
+        }
+
+        /// Pages through `ids` in booking order, skipping `offset` matches of
+        /// `status` (or of any status when `None`).
+        fn page_consultations(
+            &self,
+            ids: impl Iterator<Item = u64>,
+            status: Option<ConsultationStatus>,
+            offset: u32,
+            limit: u32,
+        ) -> Vec<Consultation> {
+            let limit = limit.min(MAX_PAGE_SIZE) as usize;
+            // Without a filter every id matches, so the skipped ones needn't be read.
+            let (skip_ids, skip_matches) = match status {
+                None => (offset as usize, 0),
+                Some(_) => (0, offset as usize),
+            };
+            ids.skip(skip_ids)
+                .filter_map(|id| self.consultations.get(id))
+                .filter(|consultation| status.as_ref().is_none_or(|s| consultation.status == *s))
+                .skip(skip_matches)
+                .take(limit)
+                .collect()
+        }
+
+        fn count_by_status(&self, ids: impl Iterator<Item = u64>) -> Vec<(ConsultationStatus, u32)> {
+            let mut counts: Vec<(ConsultationStatus, u32)> = Vec::new();
+            for consultation in ids.filter_map(|id| self.consultations.get(id)) {
+                match counts.iter_mut().find(|(status, _)| *status == consultation.status) {
+                    Some((_, count)) => *count = count.saturating_add(1),
+                    None => counts.push((consultation.status, 1)),
+                }
+            }
+            counts
+        }
+
+        #[ink(message)]
+        pub fn get_platform_fee_bps(&self) -> u16 {
+            self.platform_fee_bps
+        }
+
+        fn calculate_fee(&self, amount: Balance, fee_bps: u16) -> Result<Balance> {
+            let fee = amount
+                .checked_mul(Balance::from(fee_bps))
+                .ok_or(Error::Overflow)?
+                .checked_div(Balance::from(BPS_DENOMINATOR))
+                .ok_or(Error::Overflow)?;
+            Ok(fee)
+        }
+
+        #[ink(message)]
+        pub fn update_platform_fee(&mut self, new_fee_bps: u16) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            if new_fee_bps > BPS_DENOMINATOR {
+                return Err(Error::InvalidFee);
+            }
+            self.platform_fee_bps = new_fee_bps;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_doctor_class(&mut self, doctor: AccountId, class: Option<DoctorClass>) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            match class {
+                Some(class) => {
+                    self.doctor_classes.insert(doctor, &class);
+                }
+                None => self.doctor_classes.remove(doctor),
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_doctor_class(&self, doctor: AccountId) -> Option<DoctorClass> {
+            self.doctor_classes.get(doctor)
+        }
+
+        #[ink(message)]
+        pub fn set_specialty_fee(&mut self, specialty_code: u16, fee_bps: Option<u16>) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            match fee_bps {
+                Some(fee_bps) if fee_bps > BPS_DENOMINATOR => return Err(Error::InvalidFee),
+                Some(fee_bps) => {
+                    self.specialty_fee_bps.insert(specialty_code, &fee_bps);
+                }
+                None => self.specialty_fee_bps.remove(specialty_code),
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_tier_fee(&mut self, tier: u8, fee_bps: Option<u16>) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            match fee_bps {
+                Some(fee_bps) if fee_bps > BPS_DENOMINATOR => return Err(Error::InvalidFee),
+                Some(fee_bps) => {
+                    self.tier_fee_bps.insert(tier, &fee_bps);
+                }
+                None => self.tier_fee_bps.remove(tier),
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_fee_beneficiaries(&mut self, beneficiaries: Vec<FeeBeneficiary>) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+
+            if beneficiaries.len() > MAX_FEE_BENEFICIARIES {
+                return Err(Error::InvalidBeneficiaries);
+            }
+
+            for (index, beneficiary) in beneficiaries.iter().enumerate() {
+                let duplicate = beneficiaries[..index]
+                    .iter()
+                    .any(|other| other.account == beneficiary.account);
+                if beneficiary.weight == 0 || duplicate {
+                    return Err(Error::InvalidBeneficiaries);
+                }
+            }
+
+            self.fee_beneficiaries = beneficiaries;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_fee_beneficiaries(&self) -> Vec<FeeBeneficiary> {
+            self.fee_beneficiaries.clone()
+        }
+
+        #[ink(message)]
+        pub fn get_accrued_fees(&self, beneficiary: AccountId) -> Balance {
+            self.accrued_fees.get(beneficiary).unwrap_or(0)
+        }
+
+        #[ink(message)]
+        pub fn withdraw_fees(&mut self) -> Result<Balance> {
+            let caller = self.env().caller();
+            let amount = self.accrued_fees.get(caller).unwrap_or(0);
+
+            if amount == 0 {
+                return Err(Error::NothingToWithdraw);
+            }
+
+            if self.env().transfer(caller, amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            self.accrued_fees.remove(caller);
+
+            self.env().emit_event(FeesWithdrawn {
+                beneficiary: caller,
+                amount,
+            });
+
+            Ok(amount)
+        }
+
+        /// Pays `gross` to the doctor less the platform fee, which is credited to
+        /// the fee beneficiaries. Returns the doctor's net amount.
+        fn pay_doctor(&mut self, doctor: AccountId, gross: Balance, fee_bps: u16) -> Result<Balance> {
+            let fee_amount = self.calculate_fee(gross, fee_bps)?;
+            let doctor_amount = gross
+                .checked_sub(fee_amount)
+                .ok_or(Error::Overflow)?;
+
+            // Batched releases swallow per-item errors instead of reverting, so
+            // everything that can fail is worked out before the doctor is paid.
+            let fee_credits = self.fee_credits(fee_amount)?;
+
+            if doctor_amount > 0 && self.env().transfer(doctor, doctor_amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            for (beneficiary, accrued) in fee_credits {
+                self.accrued_fees.insert(beneficiary, &accrued);
+            }
+            self.record_released(doctor, doctor_amount, fee_amount);
+
+            Ok(doctor_amount)
+        }
+
+        /// Splits a fee across the beneficiaries by weight and returns each one's
+        /// new accrued total. Rounding dust goes to the first beneficiary; with no
+        /// beneficiaries configured the whole fee accrues to the platform wallet.
+        fn fee_credits(&self, fee_amount: Balance) -> Result<Vec<(AccountId, Balance)>> {
+            if fee_amount == 0 {
+                return Ok(Vec::new());
+            }
+
+            let shares = if self.fee_beneficiaries.is_empty() {
+                ink::prelude::vec![(self.platform_wallet, fee_amount)]
+            } else {
+                let total_weight = self.fee_beneficiaries
+                    .iter()
+                    .try_fold(0u128, |total, b| total.checked_add(u128::from(b.weight)))
+                    .ok_or(Error::Overflow)?;
+
+                let mut shares = Vec::with_capacity(self.fee_beneficiaries.len());
+                let mut distributed: Balance = 0;
+                for beneficiary in &self.fee_beneficiaries {
+                    let share = fee_amount
+                        .checked_mul(u128::from(beneficiary.weight))
+                        .ok_or(Error::Overflow)?
+                        .checked_div(total_weight)
+                        .ok_or(Error::Overflow)?;
+                    distributed = distributed.checked_add(share).ok_or(Error::Overflow)?;
+                    shares.push((beneficiary.account, share));
+                }
+
+                let dust = fee_amount.checked_sub(distributed).ok_or(Error::Overflow)?;
+                shares[0].1 = shares[0].1.checked_add(dust).ok_or(Error::Overflow)?;
+                shares
+            };
+
+            shares
+                .into_iter()
+                .map(|(account, share)| {
+                    self.get_accrued_fees(account)
+                        .checked_add(share)
+                        .map(|accrued| (account, accrued))
+                        .ok_or(Error::Overflow)
+                })
+                .collect()
+        }
+
+        /// Fee a new booking with `doctor` would be charged: a tier override wins
+        /// over a specialty override, which wins over the platform default.
+        #[ink(message)]
+        pub fn get_fee_bps_for(&self, doctor: AccountId) -> u16 {
+            let Some(class) = self.doctor_classes.get(doctor) else {
+                return self.platform_fee_bps;
+            };
+
+            self.tier_fee_bps
+                .get(class.tier)
+                .or_else(|| self.specialty_fee_bps.get(class.specialty_code))
+                .unwrap_or(self.platform_fee_bps)
+        }
+
+        #[ink(message)]
+        pub fn cancel_consultation(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.patient != caller
+                && consultation.payer != caller
+                && consultation.doctor != caller
+            {
+                return Err(Error::Unauthorized);
+            }
+
+            if consultation.status != ConsultationStatus::Pending {
+                return Err(Error::CancellationNotAllowed);
+            }
+
+            if caller == consultation.doctor {
+                return self.cancel_by_doctor(consultation_id, consultation);
+            }
+
+            let current_time = self.env().block_timestamp();
+            let time_until_consultation = consultation.scheduled_time.saturating_sub(current_time);
+
+            let refund_percent = consultation.cancellation_policy
+                .iter()
+                .find(|tier| time_until_consultation >= u64::from(tier.hours_before) * HOUR_MS)
+                .map(|tier| tier.refund_percent)
+                .unwrap_or(0);
+
+            let refund_amount = consultation.amount
+                .checked_mul(Balance::from(refund_percent))
+                .ok_or(Error::Overflow)?
+                .checked_div(100)
+                .ok_or(Error::Overflow)?;
+
+            self.refund(&consultation, refund_amount)?;
+
+            if refund_amount < consultation.amount {
+                let doctor_compensation = consultation.amount
+                    .checked_sub(refund_amount)
+                    .ok_or(Error::Overflow)?;
+                if self.env().transfer(consultation.doctor, doctor_compensation).is_err() {
+                    return Err(Error::TransferFailed);
+                }
+                self.record_released(consultation.doctor, doctor_compensation, 0);
+                self.update_patient_reliability(consultation.patient, |r| {
+                    r.late_cancellations = r.late_cancellations.saturating_add(1)
+                });
+            }
+            self.record_unlocked(Some(consultation.doctor), consultation.amount);
+            self.settle_reliability_deposit(&mut consultation, false)?;
+
+            consultation.status = ConsultationStatus::Cancelled;
+            self.consultations.insert(consultation_id, &consultation);
+            self.close_consultation(&consultation);
+            if consultation.scheduled_time > current_time {
+                self.offer_freed_slot(consultation.doctor, consultation.scheduled_time);
+            }
+
+            self.env().emit_event(ConsultationCancelled {
+                consultation_id,
+                cancelled_by: caller,
+                refund_amount,
+            });
+
+            Ok(())
+        }
+
+        fn cancel_by_doctor(
+            &mut self,
+            consultation_id: u64,
+            mut consultation: Consultation,
+        ) -> Result<()> {
+            let bond = self.doctor_bonds.get(consultation.doctor).unwrap_or(0);
+            let forfeited = bond.min(self.doctor_cancellation_penalty);
+
+            let refund_amount = consultation.amount;
+            self.refund(&consultation, refund_amount)?;
+            self.record_unlocked(Some(consultation.doctor), consultation.amount);
+
+            // The bond compensates the patient, whoever paid for the booking.
+            if forfeited > 0 {
+                if self.env().transfer(consultation.patient, forfeited).is_err() {
+                    return Err(Error::TransferFailed);
+                }
+
+                let remaining_bond = bond
+                    .checked_sub(forfeited)
+                    .ok_or(Error::Overflow)?;
+                self.doctor_bonds.insert(consultation.doctor, &remaining_bond);
+                self.sync_registry_bond(consultation.doctor, remaining_bond);
+
+                self.env().emit_event(BondForfeited {
+                    consultation_id,
+                    doctor: consultation.doctor,
+                    patient: consultation.patient,
+                    amount: forfeited,
+                });
+            }
+            self.settle_reliability_deposit(&mut consultation, false)?;
+
+            consultation.status = ConsultationStatus::Cancelled;
+            self.consultations.insert(consultation_id, &consultation);
+            self.close_consultation(&consultation);
+            if consultation.scheduled_time > self.env().block_timestamp() {
+                self.offer_freed_slot(consultation.doctor, consultation.scheduled_time);
+            }
+
+            self.notify_registry(ink::selector_bytes!("increment_cancelled"), consultation.doctor);
+
+            self.env().emit_event(ConsultationCancelled {
+                consultation_id,
+                cancelled_by: consultation.doctor,
+                refund_amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message, payable)]
+        pub fn deposit_bond(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            let amount = self.env().transferred_value();
+
+            if amount == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+
+            let total_bond = self.doctor_bonds
+                .get(caller)
+                .unwrap_or(0)
+                .checked_add(amount)
+                .ok_or(Error::Overflow)?;
+            self.doctor_bonds.insert(caller, &total_bond);
+            self.sync_registry_bond(caller, total_bond);
+
+            self.env().emit_event(BondDeposited {
+                doctor: caller,
+                amount,
+                total_bond,
+            });
+
+            Ok(())
+        }
+
+        /// Starts the cooldown after which `amount` of the bond can be withdrawn.
+        /// The bond stays slashable until the withdrawal goes through.
+        #[ink(message)]
+        pub fn request_bond_withdrawal(&mut self, amount: Balance) -> Result<()> {
+            let caller = self.env().caller();
+            let bond = self.doctor_bonds.get(caller).unwrap_or(0);
+
+            if amount == 0 || amount > bond {
+                return Err(Error::InsufficientBond);
+            }
+
+            let requested_at = self.env().block_timestamp();
+            self.bond_withdrawal_requests.insert(caller, &BondWithdrawalRequest {
+                amount,
+                requested_at,
+            });
+
+            self.env().emit_event(BondWithdrawalRequested {
+                doctor: caller,
+                amount,
+                available_at: requested_at.saturating_add(self.bond_withdrawal_cooldown),
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn cancel_bond_withdrawal(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            if !self.bond_withdrawal_requests.contains(caller) {
+                return Err(Error::WithdrawalNotRequested);
+            }
+            self.bond_withdrawal_requests.remove(caller);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn withdraw_bond(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            let request = self.bond_withdrawal_requests
+                .get(caller)
+                .ok_or(Error::WithdrawalNotRequested)?;
+
+            let available_at = request.requested_at.saturating_add(self.bond_withdrawal_cooldown);
+            if self.env().block_timestamp() < available_at {
+                return Err(Error::CooldownActive);
+            }
+
+            if self.has_open_consultations(caller) {
+                return Err(Error::OpenConsultations);
+            }
+
+            // Slashes during the cooldown shrink what is left to withdraw.
+            let bond = self.doctor_bonds.get(caller).unwrap_or(0);
+            let amount = request.amount.min(bond);
+            let remaining_bond = bond
+                .checked_sub(amount)
+                .ok_or(Error::Overflow)?;
+
+            if amount > 0 && self.env().transfer(caller, amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            self.doctor_bonds.insert(caller, &remaining_bond);
+            self.bond_withdrawal_requests.remove(caller);
+            self.sync_registry_bond(caller, remaining_bond);
+
+            self.env().emit_event(BondWithdrawn {
+                doctor: caller,
+                amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_bond_withdrawal_request(&self, doctor: AccountId) -> Option<BondWithdrawalRequest> {
+            self.bond_withdrawal_requests.get(doctor)
+        }
+
+        #[ink(message)]
+        pub fn update_bond_settings(&mut self, slash_amount: Balance, withdrawal_cooldown: u64) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            self.bond_slash_amount = slash_amount;
+            self.bond_withdrawal_cooldown = withdrawal_cooldown;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_bond_settings(&self) -> (Balance, u64) {
+            (self.bond_slash_amount, self.bond_withdrawal_cooldown)
+        }
+
+        /// Pays the configured slash out of the doctor's bond to the patient.
+        fn slash_bond(&mut self, consultation: &Consultation) -> Result<()> {
+            let bond = self.doctor_bonds.get(consultation.doctor).unwrap_or(0);
+            let slashed = bond.min(self.bond_slash_amount);
+            if slashed == 0 {
+                return Ok(());
+            }
+
+            if self.env().transfer(consultation.patient, slashed).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            let remaining_bond = bond
+                .checked_sub(slashed)
+                .ok_or(Error::Overflow)?;
+            self.doctor_bonds.insert(consultation.doctor, &remaining_bond);
+            self.sync_registry_bond(consultation.doctor, remaining_bond);
+
+            self.env().emit_event(BondSlashed {
+                consultation_id: consultation.id,
+                doctor: consultation.doctor,
+                amount: slashed,
+                remaining_bond,
+            });
+
+            Ok(())
+        }
+
+        fn has_open_consultations(&self, doctor: AccountId) -> bool {
+            self.open_consultation_count.get(doctor).unwrap_or(0) > 0
+                || self.open_case_work.get(doctor).unwrap_or(0) > 0
+        }
+
+        fn open_case_work(&mut self, doctor: AccountId) {
+            let count = self.open_case_work.get(doctor).unwrap_or(0);
+            self.open_case_work.insert(doctor, &count.saturating_add(1));
+        }
+
+        fn close_case_work(&mut self, doctor: AccountId) {
+            let count = self.open_case_work.get(doctor).unwrap_or(0);
+            self.open_case_work.insert(doctor, &count.saturating_sub(1));
+        }
+
+        /// Stops counting a consultation as open once it reaches a final status.
+        fn close_consultation(&mut self, consultation: &Consultation) {
+            let open_count = self.open_consultation_count.get(consultation.doctor).unwrap_or(0);
+            self.open_consultation_count.insert(consultation.doctor, &open_count.saturating_sub(1));
+            self.free_slot(consultation.doctor, consultation.scheduled_time);
+        }
+
+        fn book_slot(&mut self, doctor: AccountId, slot_time: u64) {
+            let count = self.booked_slots.get((doctor, slot_time)).unwrap_or(0);
+            self.booked_slots.insert((doctor, slot_time), &count.saturating_add(1));
+        }
+
+        fn free_slot(&mut self, doctor: AccountId, slot_time: u64) {
+            let count = self.booked_slots.get((doctor, slot_time)).unwrap_or(0);
+            if count > 1 {
+                self.booked_slots.insert((doctor, slot_time), &(count - 1));
+            } else {
+                self.booked_slots.remove((doctor, slot_time));
+            }
+        }
+
+        #[ink(message)]
+        pub fn get_doctor_bond(&self, doctor: AccountId) -> Balance {
+            self.doctor_bonds.get(doctor).unwrap_or(0)
+        }
+
+        #[ink(message)]
+        pub fn set_doctor_cancellation_penalty(&mut self, penalty: Balance) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            self.doctor_cancellation_penalty = penalty;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_doctor_cancellation_penalty(&self) -> Balance {
+            self.doctor_cancellation_penalty
+        }
+
+        #[ink(message)]
+        pub fn set_cancellation_policy(&mut self, tiers: Vec<RefundTier>) -> Result<()> {
+            let caller = self.env().caller();
+
+            if tiers.is_empty() || tiers.len() > MAX_POLICY_TIERS {
+                return Err(Error::InvalidCancellationPolicy);
+            }
+
+            for (index, tier) in tiers.iter().enumerate() {
+                if tier.hours_before > MAX_NOTICE_HOURS
+                    || tier.refund_percent > 100
+                    || tier.refund_percent < self.min_refund_percent
+                {
+                    return Err(Error::InvalidCancellationPolicy);
+                }
+
+                // Tiers are matched in order, so notice periods must strictly decrease.
+                if index > 0 && tier.hours_before >= tiers[index - 1].hours_before {
+                    return Err(Error::InvalidCancellationPolicy);
+                }
+            }
+
+            // The last tier must cover cancellations up to the consultation itself,
+            // otherwise late cancellations would fall below the minimum refund.
+            if tiers.last().is_some_and(|tier| tier.hours_before != 0) {
+                return Err(Error::InvalidCancellationPolicy);
+            }
+
+            self.cancellation_policies.insert(caller, &tiers);
+
+            self.env().emit_event(CancellationPolicyUpdated {
+                doctor: caller,
+                tiers_count: u32::try_from(tiers.len()).unwrap_or(u32::MAX),
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_cancellation_policy(&self, doctor: AccountId) -> Vec<RefundTier> {
+            self.cancellation_policies
+                .get(doctor)
+                .unwrap_or_else(|| ink::prelude::vec![
+                    RefundTier { hours_before: 24, refund_percent: 100 },
+                    RefundTier { hours_before: 0, refund_percent: 50 },
+                ])
+        }
+
+        #[ink(message)]
+        pub fn set_min_refund_percent(&mut self, min_refund_percent: u8) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            if min_refund_percent > 100 {
+                return Err(Error::InvalidCancellationPolicy);
+            }
+            self.min_refund_percent = min_refund_percent;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_min_refund_percent(&self) -> u8 {
+            self.min_refund_percent
+        }
+
+        #[ink(message)]
+        pub fn report_no_show(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            let current_time = self.env().block_timestamp();
+            let grace_ends_at = consultation.scheduled_time.saturating_add(self.no_show_grace_period);
+            if current_time <= grace_ends_at {
+                return Err(Error::TooEarlyToRelease);
+            }
+
+            if consultation.status != ConsultationStatus::Pending 
+                && consultation.status != ConsultationStatus::InProgress {
+                return Err(Error::InvalidStatus);
+            }
+
+            // The claim only stands if the patient showed up and nothing on record
+            // says the doctor did in time. A start the doctor attested alone, after
+            // the window, proves nothing; one the patient co-signed does.
+            let patient_showed = consultation.patient_checked_in_at
+                .is_some_and(|at| self.in_check_in_window(&consultation, at));
+            let doctor_showed = consultation.doctor_checked_in_at
+                .is_some_and(|at| self.in_check_in_window(&consultation, at))
+                || (consultation.attestation.start_by_doctor
+                    && consultation.attestation.start_by_patient);
+            if !patient_showed || doctor_showed {
+                return Err(Error::NoShowClaimRejected);
+            }
+
+            self.refund(&consultation, consultation.amount)?;
+            self.record_unlocked(Some(consultation.doctor), consultation.amount);
+            self.refund_overtime_allowance(&mut consultation)?;
+            self.slash_bond(&consultation)?;
+            self.settle_reliability_deposit(&mut consultation, false)?;
+
+            consultation.status = ConsultationStatus::NoShow;
+            self.consultations.insert(consultation_id, &consultation);
+            self.close_consultation(&consultation);
+
+            self.notify_registry(ink::selector_bytes!("increment_no_show"), consultation.doctor);
+
+            self.env().emit_event(NoShowReported {
+                consultation_id,
+                doctor: consultation.doctor,
+            });
+
+            Ok(())
+        }
+
+        /// Check-ins open shortly before the scheduled time and close when the
+        /// no-show grace period ends.
+        fn in_check_in_window(&self, consultation: &Consultation, at: u64) -> bool {
+            let opens_at = consultation.scheduled_time.saturating_sub(CHECK_IN_OPENS_BEFORE_MS);
+            let closes_at = consultation.scheduled_time.saturating_add(self.no_show_grace_period);
+            (opens_at..=closes_at).contains(&at)
+        }
+
+        #[ink(message)]
+        pub fn check_in(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.patient != caller && consultation.doctor != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if consultation.status != ConsultationStatus::Pending
+                && consultation.status != ConsultationStatus::InProgress {
+                return Err(Error::InvalidStatus);
+            }
+
+            let checked_in_at = if caller == consultation.patient {
+                consultation.patient_checked_in_at
+            } else {
+                consultation.doctor_checked_in_at
+            };
+            if checked_in_at.is_some() {
+                return Err(Error::AlreadyCheckedIn);
+            }
+
+            let current_time = self.env().block_timestamp();
+            if !self.in_check_in_window(&consultation, current_time) {
+                return Err(Error::CheckInClosed);
+            }
+
+            if caller == consultation.patient {
+                consultation.patient_checked_in_at = Some(current_time);
+            } else {
+                consultation.doctor_checked_in_at = Some(current_time);
+            }
+            self.consultations.insert(consultation_id, &consultation);
+
+            self.env().emit_event(CheckedIn {
+                consultation_id,
+                account: caller,
+                checked_in_at: current_time,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn report_patient_no_show(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.doctor != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if consultation.status != ConsultationStatus::Pending {
+                return Err(Error::InvalidStatus);
+            }
+
+            let current_time = self.env().block_timestamp();
+            let grace_ends_at = consultation.scheduled_time.saturating_add(self.no_show_grace_period);
+            if current_time <= grace_ends_at {
+                return Err(Error::TooEarlyToRelease);
+            }
+
+            if consultation.doctor_checked_in_at.is_none()
+                || consultation.patient_checked_in_at.is_some()
+                || consultation.attestation.start_by_patient
+            {
+                return Err(Error::NoShowClaimRejected);
+            }
+
+            let doctor_amount = consultation.amount
+                .checked_mul(Balance::from(self.patient_no_show_doctor_percent))
+                .ok_or(Error::Overflow)?
+                .checked_div(100)
+                .ok_or(Error::Overflow)?;
+            let refund_amount = consultation.amount
+                .checked_sub(doctor_amount)
+                .ok_or(Error::Overflow)?;
+
+            if doctor_amount > 0
+                && self.env().transfer(consultation.doctor, doctor_amount).is_err()
+            {
+                return Err(Error::TransferFailed);
+            }
+            self.record_released(consultation.doctor, doctor_amount, 0);
+
+            self.refund(&consultation, refund_amount)?;
+            self.record_unlocked(Some(consultation.doctor), consultation.amount);
+            self.settle_reliability_deposit(&mut consultation, true)?;
+            self.update_patient_reliability(consultation.patient, |r| {
+                r.no_shows = r.no_shows.saturating_add(1)
+            });
+
+            consultation.status = ConsultationStatus::PatientNoShow;
+            self.consultations.insert(consultation_id, &consultation);
+            self.close_consultation(&consultation);
+
+            self.env().emit_event(PatientNoShowReported {
+                consultation_id,
+                patient: consultation.patient,
+                doctor_amount,
+                refund_amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn attest_session(&mut self, consultation_id: u64, phase: SessionPhase) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.patient != caller && consultation.doctor != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            let allowed = match phase {
+                SessionPhase::Start => consultation.status == ConsultationStatus::InProgress,
+                SessionPhase::End => consultation.status == ConsultationStatus::InProgress
+                    || consultation.status == ConsultationStatus::Completed,
+            };
+            if !allowed {
+                return Err(Error::InvalidStatus);
+            }
+
+            let is_patient = caller == consultation.patient;
+            let attestation = &mut consultation.attestation;
+            let cosigned = match (phase, is_patient) {
+                (SessionPhase::Start, true) => {
+                    attestation.start_by_patient = true;
+                    attestation.start_by_doctor
+                }
+                (SessionPhase::Start, false) => {
+                    attestation.start_by_doctor = true;
+                    attestation.start_by_patient
+                }
+                (SessionPhase::End, true) => {
+                    attestation.end_by_patient = true;
+                    attestation.end_by_doctor
+                }
+                (SessionPhase::End, false) => {
+                    attestation.end_by_doctor = true;
+                    attestation.end_by_patient
+                }
+            };
+            self.consultations.insert(consultation_id, &consultation);
+
+            self.env().emit_event(SessionAttested {
+                consultation_id,
+                account: caller,
+                phase,
+                cosigned,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn expire_consultation(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            let (deadline, doctor_percent) = match consultation.status {
+                ConsultationStatus::Pending => (self.sla_config.start_deadline, 0),
+                ConsultationStatus::InProgress => (
+                    self.sla_config.completion_deadline,
+                    self.sla_config.in_progress_doctor_percent,
+                ),
+                _ => return Err(Error::InvalidStatus),
+            };
+
+            let expires_at = consultation.scheduled_time.saturating_add(deadline);
+            if self.env().block_timestamp() <= expires_at {
+                return Err(Error::DeadlineNotReached);
+            }
+
+            // Only a patient who showed up for a session that never started
+            // counts as a confirmed doctor no-show.
+            let doctor_no_show = consultation.status == ConsultationStatus::Pending
+                && consultation.patient_checked_in_at.is_some()
+                && consultation.doctor_checked_in_at.is_none();
+
+            let doctor_share = consultation.amount
+                .checked_mul(Balance::from(doctor_percent))
+                .ok_or(Error::Overflow)?
+                .checked_div(100)
+                .ok_or(Error::Overflow)?;
+            let refund_amount = consultation.amount
+                .checked_sub(doctor_share)
+                .ok_or(Error::Overflow)?;
+
+            // The keeper is paid by the doctor, never out of the patient's refund:
+            // from the bond of a doctor who never started the session, or from
+            // the doctor's share of a started one.
+            let (keeper_reward, doctor_gross) = if consultation.status == ConsultationStatus::Pending {
+                let bond = self.doctor_bonds.get(consultation.doctor).unwrap_or(0);
+                let keeper_reward = self.sla_config.keeper_reward.min(bond);
+                if keeper_reward > 0 {
+                    let remaining_bond = bond
+                        .checked_sub(keeper_reward)
+                        .ok_or(Error::Overflow)?;
+                    self.doctor_bonds.insert(consultation.doctor, &remaining_bond);
+                    self.sync_registry_bond(consultation.doctor, remaining_bond);
+                }
+                (keeper_reward, doctor_share)
+            } else {
+                let keeper_reward = self.sla_config.keeper_reward.min(doctor_share);
+                self.record_keeper_reward(consultation.doctor, keeper_reward);
+                let doctor_gross = doctor_share
+                    .checked_sub(keeper_reward)
+                    .ok_or(Error::Overflow)?;
+                (keeper_reward, doctor_gross)
+            };
+
+            if keeper_reward > 0 && self.env().transfer(caller, keeper_reward).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            let doctor_amount = if doctor_gross > 0 {
+                self.pay_doctor(consultation.doctor, doctor_gross, consultation.fee_bps)?
+            } else {
+                0
+            };
+
+            self.refund(&consultation, refund_amount)?;
+            self.record_unlocked(Some(consultation.doctor), consultation.amount);
+
+            self.refund_overtime_allowance(&mut consultation)?;
+
+            if doctor_no_show {
+                self.slash_bond(&consultation)?;
+                self.notify_registry(ink::selector_bytes!("increment_no_show"), consultation.doctor);
+            }
+            self.settle_reliability_deposit(&mut consultation, false)?;
+
+            consultation.status = ConsultationStatus::Expired;
+            self.consultations.insert(consultation_id, &consultation);
+            self.close_consultation(&consultation);
+
+            self.env().emit_event(ConsultationExpired {
+                consultation_id,
+                keeper: caller,
+                keeper_reward,
+                doctor_amount,
+                refund_amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn update_sla_config(&mut self, config: SlaConfig) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+
+            // Expiry must not pre-empt the no-show claims, which open after the grace period.
+            if config.in_progress_doctor_percent > 100
+                || config.start_deadline <= self.no_show_grace_period
+                || config.completion_deadline < config.start_deadline
+            {
+                return Err(Error::InvalidSlaConfig);
+            }
+
+            self.sla_config = config;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_sla_config(&self) -> SlaConfig {
+            self.sla_config.clone()
+        }
+
+        #[ink(message)]
+        pub fn update_no_show_settings(
+            &mut self,
+            grace_period: u64,
+            patient_no_show_doctor_percent: u8,
+        ) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            if patient_no_show_doctor_percent > 100 {
+                return Err(Error::InvalidPercentage);
+            }
+            if grace_period >= self.sla_config.start_deadline {
+                return Err(Error::InvalidSlaConfig);
+            }
+            self.no_show_grace_period = grace_period;
+            self.patient_no_show_doctor_percent = patient_no_show_doctor_percent;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_no_show_settings(&self) -> (u64, u8) {
+            (self.no_show_grace_period, self.patient_no_show_doctor_percent)
+        }
+
+        #[ink(message, payable)]
+        pub fn book_verified_consultation(
+            &mut self,
+            doctor: AccountId,
+            scheduled_time: u64,
+        ) -> Result<u64> {
+            self.book_consultation(doctor, scheduled_time)
+        }
+
+        #[ink(message)]
+        pub fn propose_reschedule(&mut self, consultation_id: u64, new_time: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.patient != caller && consultation.doctor != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if consultation.status != ConsultationStatus::Pending {
+                return Err(Error::InvalidStatus);
+            }
+
+            if consultation.reschedule_count >= MAX_RESCHEDULES {
+                return Err(Error::RescheduleLimitReached);
+            }
+
+            let current_time = self.env().block_timestamp();
+            if new_time <= current_time
+                || new_time > current_time.saturating_add(MAX_BOOKING_HORIZON)
+                || new_time == consultation.scheduled_time
+            {
+                return Err(Error::InvalidScheduleTime);
+            }
+
+            let proposal = RescheduleProposal {
+                proposed_by: caller,
+                new_time,
+                proposed_at: current_time,
+            };
+            self.reschedule_proposals.insert(consultation_id, &proposal);
+
+            self.env().emit_event(RescheduleProposed {
+                consultation_id,
+                proposed_by: caller,
+                new_time,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn accept_reschedule(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+            let proposal = self.reschedule_proposals
+                .get(consultation_id)
+                .ok_or(Error::NoRescheduleProposal)?;
+
+            if consultation.patient != caller && consultation.doctor != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            // Consent has to come from the party that did not make the proposal.
+            if proposal.proposed_by == caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if consultation.status != ConsultationStatus::Pending {
+                return Err(Error::InvalidStatus);
+            }
+
+            if proposal.new_time <= self.env().block_timestamp() {
+                return Err(Error::InvalidScheduleTime);
+            }
+
+            // A slot held for a waitlisted patient can't be taken by moving
+            // another booking onto it.
+            if let Some(offer) = self.slot_offers.get((consultation.doctor, proposal.new_time)) {
+                if offer.patient != consultation.patient {
+                    if self.env().block_timestamp() <= offer.expires_at {
+                        return Err(Error::SlotNotAvailable);
+                    }
+                    self.drop_lapsed_offer(consultation.doctor, proposal.new_time, &offer)?;
+                }
+            }
+
+            // Check-ins and attestations belong to the old appointment; no-show
+            // decisions at the new time must not rely on them.
+            let old_time = consultation.scheduled_time;
+            self.free_slot(consultation.doctor, old_time);
+            self.book_slot(consultation.doctor, proposal.new_time);
+            consultation.scheduled_time = proposal.new_time;
+            consultation.patient_checked_in_at = None;
+            consultation.doctor_checked_in_at = None;
+            consultation.attestation = SessionAttestation::default();
+            consultation.reschedule_count = consultation.reschedule_count
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+            self.consultations.insert(consultation_id, &consultation);
+            self.reschedule_proposals.remove(consultation_id);
+
+            self.update_registry_slot(
+                ink::selector_bytes!("mark_slot_booked"),
+                consultation.doctor,
+                proposal.new_time,
+            );
+            self.update_registry_slot(
+                ink::selector_bytes!("mark_slot_available"),
+                consultation.doctor,
+                old_time,
+            );
+
+            if old_time > self.env().block_timestamp() {
+                self.offer_freed_slot(consultation.doctor, old_time);
+            }
+
+            self.env().emit_event(ConsultationRescheduled {
+                consultation_id,
+                old_time,
+                new_time: proposal.new_time,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn decline_reschedule(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if consultation.patient != caller && consultation.doctor != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if !self.reschedule_proposals.contains(consultation_id) {
+                return Err(Error::NoRescheduleProposal);
+            }
+
+            self.reschedule_proposals.remove(consultation_id);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_reschedule_proposal(&self, consultation_id: u64) -> Option<RescheduleProposal> {
+            self.reschedule_proposals.get(consultation_id)
+        }
+
+        #[ink(message)]
+        pub fn set_package_offer(&mut self, sessions: u32, price: Option<Balance>) -> Result<()> {
+            let caller = self.env().caller();
+
+            if sessions == 0 || sessions > MAX_PACKAGE_SESSIONS {
+                return Err(Error::InvalidPackage);
+            }
+
+            match price {
+                Some(0) => return Err(Error::InvalidPackage),
+                Some(price) => {
+                    self.package_offers.insert((caller, sessions), &price);
+                }
+                None => self.package_offers.remove((caller, sessions)),
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_package_price(&self, doctor: AccountId, sessions: u32) -> Option<Balance> {
+            self.package_offers.get((doctor, sessions))
+        }
+
+        #[ink(message, payable)]
+        pub fn purchase_package(&mut self, doctor: AccountId, sessions: u32) -> Result<u64> {
+            let patient = self.env().caller();
+            let paid = self.env().transferred_value();
+
+            let price = self.package_offers
+                .get((doctor, sessions))
+                .ok_or(Error::InvalidPackage)?;
+            if paid != price {
+                return Err(Error::IncorrectPayment);
+            }
+
+            let package_id = self.next_package_id;
+            let package = CarePackage {
+                id: package_id,
+                patient,
+                doctor,
+                price,
+                sessions_total: sessions,
+                sessions_used: 0,
+                value_used: 0,
+                status: PackageStatus::Active,
+                created_at: self.env().block_timestamp(),
+            };
+            self.packages.insert(package_id, &package);
+            self.record_locked(Some(doctor), price);
+
+            self.next_package_id = self.next_package_id
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            self.env().emit_event(PackagePurchased {
+                package_id,
+                patient,
+                doctor,
+                sessions,
+                price,
+            });
+
+            Ok(package_id)
+        }
+
+        /// Books a session from a package. A patient who owes a reliability
+        /// deposit attaches it to this call.
+        #[ink(message, payable)]
+        pub fn book_with_package(&mut self, package_id: u64, scheduled_time: u64) -> Result<u64> {
+            let caller = self.env().caller();
+            let mut package = self.packages
+                .get(package_id)
+                .ok_or(Error::PackageNotFound)?;
+
+            if package.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if package.status != PackageStatus::Active {
+                return Err(Error::PackageNotActive);
+            }
+
+            let remaining_sessions = package.sessions_total
+                .checked_sub(package.sessions_used)
+                .ok_or(Error::NoCreditsRemaining)?;
+            if remaining_sessions == 0 {
+                return Err(Error::NoCreditsRemaining);
+            }
+
+            // Every credit is worth the same, except the last one which also
+            // carries the rounding remainder of the package price.
+            let remaining_value = package.price
+                .checked_sub(package.value_used)
+                .ok_or(Error::Overflow)?;
+            let session_value = if remaining_sessions == 1 {
+                remaining_value
+            } else {
+                package.price
+                    .checked_div(Balance::from(package.sessions_total))
+                    .ok_or(Error::Overflow)?
+            };
+
+            // The credit moves from the package into the consultation, which
+            // locks it again.
+            self.record_unlocked(Some(package.doctor), session_value);
+            let paid = self.env().transferred_value();
+            if paid > self.get_required_deposit(package.doctor, caller) {
+                return Err(Error::IncorrectPayment);
+            }
+            let consultation_id = self.create_consultation(
+                caller,
+                caller,
+                package.doctor,
+                scheduled_time,
+                session_value.checked_add(paid).ok_or(Error::Overflow)?,
+                paid,
+            )?;
+
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+            consultation.package_id = Some(package_id);
+            self.consultations.insert(consultation_id, &consultation);
+
+            package.sessions_used = package.sessions_used
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+            package.value_used = package.value_used
+                .checked_add(session_value)
+                .ok_or(Error::Overflow)?;
+            if package.sessions_used == package.sessions_total {
+                package.status = PackageStatus::Exhausted;
+            }
+            self.packages.insert(package_id, &package);
+
+            Ok(consultation_id)
+        }
+
+        #[ink(message)]
+        pub fn cancel_package(&mut self, package_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut package = self.packages
+                .get(package_id)
+                .ok_or(Error::PackageNotFound)?;
+
+            if package.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if package.status != PackageStatus::Active {
+                return Err(Error::PackageNotActive);
+            }
+
+            let refund_amount = package.price
+                .checked_sub(package.value_used)
+                .ok_or(Error::Overflow)?;
+
+            if refund_amount > 0 && self.env().transfer(package.patient, refund_amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            self.record_refunded(Some(package.doctor), refund_amount);
+            self.record_unlocked(Some(package.doctor), refund_amount);
+
+            package.status = PackageStatus::Cancelled;
+            self.packages.insert(package_id, &package);
+
+            self.env().emit_event(PackageCancelled {
+                package_id,
+                refund_amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_package(&self, package_id: u64) -> Option<CarePackage> {
+            self.packages.get(package_id)
+        }
+
+        #[ink(message)]
+        pub fn create_subscription_plan(
+            &mut self,
+            price_per_period: Balance,
+            period: u64,
+            terms_ipfs_hash: String,
+        ) -> Result<u64> {
+            let doctor = self.env().caller();
+
+            if price_per_period == 0 || period == 0 {
+                return Err(Error::InvalidPlan);
+            }
+
+            let plan_id = self.next_plan_id;
+            let plan = SubscriptionPlan {
+                id: plan_id,
+                doctor,
+                price_per_period,
+                period,
+                terms_ipfs_hash,
+                active: true,
+            };
+            self.subscription_plans.insert(plan_id, &plan);
+
+            self.next_plan_id = self.next_plan_id
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            Ok(plan_id)
+        }
+
+        #[ink(message)]
+        pub fn deactivate_subscription_plan(&mut self, plan_id: u64) -> Result<()> {
+            let mut plan = self.subscription_plans
+                .get(plan_id)
+                .ok_or(Error::PlanNotFound)?;
+
+            if plan.doctor != self.env().caller() {
+                return Err(Error::Unauthorized);
+            }
+
+            plan.active = false;
+            self.subscription_plans.insert(plan_id, &plan);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_subscription_plan(&self, plan_id: u64) -> Option<SubscriptionPlan> {
+            self.subscription_plans.get(plan_id)
+        }
+
+        #[ink(message, payable)]
+        pub fn subscribe(&mut self, plan_id: u64) -> Result<u64> {
+            let patient = self.env().caller();
+            let plan = self.subscription_plans
+                .get(plan_id)
+                .ok_or(Error::PlanNotFound)?;
+
+            if !plan.active {
+                return Err(Error::PlanInactive);
+            }
+
+            if self.env().transferred_value() != plan.price_per_period {
+                return Err(Error::IncorrectPayment);
+            }
+
+            let subscription_id = self.next_subscription_id;
+            let subscription = Subscription {
+                id: subscription_id,
+                plan_id,
+                patient,
+                doctor: plan.doctor,
+                price_per_period: plan.price_per_period,
+                period: plan.period,
+                fee_bps: self.get_fee_bps_for(plan.doctor),
+                started_at: self.env().block_timestamp(),
+                prepaid_periods: 1,
+                released_periods: 0,
+                status: SubscriptionStatus::Active,
+            };
+            self.subscriptions.insert(subscription_id, &subscription);
+            self.record_locked(Some(plan.doctor), plan.price_per_period);
+
+            self.next_subscription_id = self.next_subscription_id
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            self.env().emit_event(SubscriptionStarted {
+                subscription_id,
+                patient,
+                doctor: plan.doctor,
+                plan_id,
+            });
+
+            Ok(subscription_id)
+        }
+
+        #[ink(message, payable)]
+        pub fn renew_subscription(&mut self, subscription_id: u64) -> Result<()> {
+            let mut subscription = self.subscriptions
+                .get(subscription_id)
+                .ok_or(Error::SubscriptionNotFound)?;
+
+            if subscription.patient != self.env().caller() {
+                return Err(Error::Unauthorized);
+            }
+
+            if subscription.status != SubscriptionStatus::Active {
+                return Err(Error::SubscriptionNotActive);
+            }
+
+            if self.env().transferred_value() != subscription.price_per_period {
+                return Err(Error::IncorrectPayment);
+            }
+
+            // Periods are counted from `started_at`, so paying after a lapse would
+            // only buy periods that are already over. A lapsed plan needs a new
+            // subscription.
+            let outstanding = subscription.prepaid_periods
+                .saturating_sub(self.ended_periods(&subscription));
+            if outstanding == 0 {
+                return Err(Error::SubscriptionLapsed);
+            }
+            if outstanding >= MAX_PREPAID_PERIODS {
+                return Err(Error::TooManyPrepaidPeriods);
+            }
+
+            subscription.prepaid_periods = subscription.prepaid_periods
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+            self.subscriptions.insert(subscription_id, &subscription);
+            self.record_locked(Some(subscription.doctor), subscription.price_per_period);
+
+            self.env().emit_event(SubscriptionRenewed {
+                subscription_id,
+                prepaid_periods: subscription.prepaid_periods,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn release_subscription_periods(&mut self, subscription_id: u64) -> Result<Balance> {
+            let mut subscription = self.subscriptions
+                .get(subscription_id)
+                .ok_or(Error::SubscriptionNotFound)?;
+
+            let ended = self.ended_periods(&subscription).min(subscription.prepaid_periods);
+            let periods = ended.saturating_sub(subscription.released_periods);
+            if periods == 0 {
+                return Err(Error::NothingToRelease);
+            }
+
+            let gross = subscription.price_per_period
+                .checked_mul(Balance::from(periods))
+                .ok_or(Error::Overflow)?;
+            let doctor_amount = self.pay_doctor(subscription.doctor, gross, subscription.fee_bps)?;
+            self.record_unlocked(Some(subscription.doctor), gross);
+
+            subscription.released_periods = ended;
+            self.subscriptions.insert(subscription_id, &subscription);
+
+            self.env().emit_event(SubscriptionPeriodsReleased {
+                subscription_id,
+                periods,
+                doctor_amount,
+            });
+
+            Ok(doctor_amount)
+        }
+
+        #[ink(message)]
+        pub fn cancel_subscription(&mut self, subscription_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut subscription = self.subscriptions
+                .get(subscription_id)
+                .ok_or(Error::SubscriptionNotFound)?;
+
+            if subscription.patient != caller && subscription.doctor != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if subscription.status != SubscriptionStatus::Active {
+                return Err(Error::SubscriptionNotActive);
+            }
+
+            let current_index = self.ended_periods(&subscription);
+            let ended = current_index.min(subscription.prepaid_periods);
+            let unreleased = ended.saturating_sub(subscription.released_periods);
+
+            let mut doctor_gross = subscription.price_per_period
+                .checked_mul(Balance::from(unreleased))
+                .ok_or(Error::Overflow)?;
+            let mut refund_amount: Balance = 0;
+
+            if current_index < subscription.prepaid_periods {
+                // The running period is split by the time elapsed in it; any period
+                // prepaid beyond it is refunded in full.
+                let period_start = u64::from(current_index)
+                    .checked_mul(subscription.period)
+                    .and_then(|offset| subscription.started_at.checked_add(offset))
+                    .ok_or(Error::Overflow)?;
+                let elapsed = self.env().block_timestamp().saturating_sub(period_start);
+                let used = subscription.price_per_period
+                    .checked_mul(Balance::from(elapsed))
+                    .ok_or(Error::Overflow)?
+                    .checked_div(Balance::from(subscription.period))
+                    .ok_or(Error::Overflow)?;
+                let future_periods = subscription.prepaid_periods - current_index - 1;
+
+                doctor_gross = doctor_gross.checked_add(used).ok_or(Error::Overflow)?;
+                refund_amount = subscription.price_per_period
+                    .checked_sub(used)
+                    .and_then(|unused| {
+                        subscription.price_per_period
+                            .checked_mul(Balance::from(future_periods))
+                            .and_then(|future| unused.checked_add(future))
+                    })
+                    .ok_or(Error::Overflow)?;
+            }
+
+            if doctor_gross > 0 {
+                self.pay_doctor(subscription.doctor, doctor_gross, subscription.fee_bps)?;
+            }
+
+            if refund_amount > 0
+                && self.env().transfer(subscription.patient, refund_amount).is_err()
+            {
+                return Err(Error::TransferFailed);
+            }
+            self.record_refunded(Some(subscription.doctor), refund_amount);
+            self.record_unlocked(
+                Some(subscription.doctor),
+                doctor_gross.checked_add(refund_amount).ok_or(Error::Overflow)?,
+            );
+
+            subscription.released_periods = subscription.prepaid_periods;
+            subscription.status = SubscriptionStatus::Cancelled;
+            self.subscriptions.insert(subscription_id, &subscription);
+
+            self.env().emit_event(SubscriptionCancelled {
+                subscription_id,
+                cancelled_by: caller,
+                refund_amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_subscription(&self, subscription_id: u64) -> Option<Subscription> {
+            self.subscriptions.get(subscription_id)
+        }
+
+        /// Number of whole periods that have ended since the subscription started.
+        fn ended_periods(&self, subscription: &Subscription) -> u32 {
+            let elapsed = self.env()
+                .block_timestamp()
+                .saturating_sub(subscription.started_at);
+            let periods = elapsed.checked_div(subscription.period).unwrap_or(0);
+            u32::try_from(periods).unwrap_or(u32::MAX)
+        }
+
+        #[ink(message)]
+        pub fn set_follow_up_policy(&mut self, policy: FollowUpPolicy) -> Result<()> {
+            if policy.discount_percent > 100 {
+                return Err(Error::InvalidPercentage);
+            }
+            self.follow_up_policies.insert(self.env().caller(), &policy);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_follow_up_policy(&self, doctor: AccountId) -> FollowUpPolicy {
+            self.follow_up_policies
+                .get(doctor)
+                .unwrap_or(FollowUpPolicy {
+                    window: 14 * 24 * HOUR_MS,
+                    discount_percent: 0,
+                })
+        }
+
+        #[ink(message, payable)]
+        pub fn book_follow_up(&mut self, parent_id: u64, scheduled_time: u64) -> Result<u64> {
+            let caller = self.env().caller();
+            let parent = self.consultations
+                .get(parent_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if parent.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if parent.status != ConsultationStatus::Completed
+                && parent.status != ConsultationStatus::Released
+            {
+                return Err(Error::InvalidStatus);
+            }
+
+            let policy = self.get_follow_up_policy(parent.doctor);
+            let completed_at = parent.completed_at.ok_or(Error::InvalidStatus)?;
+            if self.env().block_timestamp() > completed_at.saturating_add(policy.window) {
+                return Err(Error::FollowUpWindowExpired);
+            }
+
+            let mut siblings = self.follow_ups.get(parent_id).unwrap_or_default();
+            if siblings.len() >= MAX_FOLLOW_UPS {
+                return Err(Error::TooManyFollowUps);
+            }
+
+            let amount = parent.amount
+                .checked_mul(Balance::from(100 - policy.discount_percent))
+                .ok_or(Error::Overflow)?
+                .checked_div(100)
+                .ok_or(Error::Overflow)?;
+            let paid = self.env().transferred_value();
+            let deposit = self.get_required_deposit(parent.doctor, caller);
+            if Some(paid) != amount.checked_add(deposit) {
+                return Err(Error::IncorrectPayment);
+            }
+
+            let consultation_id = self.create_consultation(
+                caller,
+                caller,
+                parent.doctor,
+                scheduled_time,
+                paid,
+                paid,
+            )?;
+
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+            consultation.parent_id = Some(parent_id);
+            self.consultations.insert(consultation_id, &consultation);
+
+            siblings.push(consultation_id);
+            self.follow_ups.insert(parent_id, &siblings);
+
+            self.env().emit_event(FollowUpBooked {
+                consultation_id,
+                parent_id,
+                amount,
+            });
+
+            Ok(consultation_id)
+        }
+
+        #[ink(message)]
+        pub fn get_follow_ups(&self, consultation_id: u64) -> Vec<u64> {
+            self.follow_ups.get(consultation_id).unwrap_or_default()
+        }
+
+        /// Every consultation in the same follow-up chain, starting from the
+        /// original visit and listed breadth-first.
+        #[ink(message)]
+        pub fn get_follow_up_chain(&self, consultation_id: u64) -> Vec<u64> {
+            let mut root = consultation_id;
+            while let Some(parent_id) = self.consultations.get(root).and_then(|c| c.parent_id) {
+                root = parent_id;
+            }
+
+            let mut chain = ink::prelude::vec![root];
+            let mut index = 0;
+            while index < chain.len() && chain.len() < MAX_CHAIN_LENGTH {
+                chain.extend(self.get_follow_ups(chain[index]));
+                index += 1;
+            }
+            chain.truncate(MAX_CHAIN_LENGTH);
+            chain
+        }
+
+        #[ink(message, payable)]
+        pub fn book_case_conference(
+            &mut self,
+            patient: AccountId,
+            shares: Vec<(AccountId, u16)>,
+            scheduled_time: u64,
+        ) -> Result<u64> {
+            let payer = self.env().caller();
+            let amount = self.env().transferred_value();
+
+            if amount == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+
+            if shares.len() < 2 || shares.len() > MAX_CONFERENCE_DOCTORS {
+                return Err(Error::InvalidParticipants);
+            }
+
+            let mut total_bps: u32 = 0;
+            for (index, (doctor, share_bps)) in shares.iter().enumerate() {
+                let duplicate = shares[..index].iter().any(|(other, _)| other == doctor);
+                if *share_bps == 0 || duplicate || *doctor == patient {
+                    return Err(Error::InvalidParticipants);
+                }
+                total_bps += u32::from(*share_bps);
+            }
+            if total_bps != u32::from(BPS_DENOMINATOR) {
+                return Err(Error::InvalidParticipants);
+            }
+
+            let participants: Vec<ConferenceParticipant> = shares
+                .into_iter()
+                .map(|(doctor, share_bps)| ConferenceParticipant {
+                    doctor,
+                    share_bps,
+                    fee_bps: self.get_fee_bps_for(doctor),
+                    confirmed: false,
+                })
+                .collect();
+            let doctors_count = u32::try_from(participants.len()).unwrap_or(u32::MAX);
+
+            let conference_id = self.next_conference_id;
+            let conference = CaseConference {
+                id: conference_id,
+                patient,
+                payer,
+                amount,
+                participants,
+                status: ConferenceStatus::Pending,
+                scheduled_time,
+                created_at: self.env().block_timestamp(),
+                completed_at: None,
+                confirm_by: scheduled_time.saturating_add(CONFERENCE_CONFIRMATION_WINDOW),
+            };
+            self.conferences.insert(conference_id, &conference);
+            for (participant, share) in conference.participants.iter().zip(Self::conference_shares(&conference)?) {
+                self.record_locked(Some(participant.doctor), share);
+                self.open_case_work(participant.doctor);
+            }
+
+            self.next_conference_id = self.next_conference_id
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            self.env().emit_event(ConferenceBooked {
+                conference_id,
+                patient,
+                doctors_count,
+                amount,
+            });
+
+            Ok(conference_id)
+        }
+
+        #[ink(message)]
+        pub fn confirm_conference_completion(&mut self, conference_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut conference = self.conferences
+                .get(conference_id)
+                .ok_or(Error::ConferenceNotFound)?;
+
+            if conference.status != ConferenceStatus::Pending {
+                return Err(Error::InvalidStatus);
+            }
+
+            let participant = conference.participants
+                .iter_mut()
+                .find(|participant| participant.doctor == caller)
+                .ok_or(Error::Unauthorized)?;
+            if participant.confirmed {
+                return Err(Error::AlreadyConfirmed);
+            }
+            participant.confirmed = true;
+
+            let all_confirmed = conference.participants.iter().all(|p| p.confirmed);
+            if all_confirmed {
+                conference.status = ConferenceStatus::Completed;
+                conference.completed_at = Some(self.env().block_timestamp());
+            }
+            self.conferences.insert(conference_id, &conference);
+
+            self.env().emit_event(ConferenceCompletionConfirmed {
+                conference_id,
+                doctor: caller,
+                all_confirmed,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn release_conference_payment(&mut self, conference_id: u64) -> Result<()> {
+            let mut conference = self.conferences
+                .get(conference_id)
+                .ok_or(Error::ConferenceNotFound)?;
+
+            if conference.status != ConferenceStatus::Completed {
+                return Err(Error::InvalidStatus);
+            }
+
+            let completed_at = conference.completed_at.ok_or(Error::InvalidStatus)?;
+            let current_time = self.env().block_timestamp();
+            if current_time.saturating_sub(completed_at) < 24 * HOUR_MS
+                && self.env().caller() != self.owner
+            {
+                return Err(Error::TooEarlyToRelease);
+            }
+
+            let shares = Self::conference_shares(&conference)?;
+            for (participant, gross) in conference.participants.iter().zip(shares) {
+                self.pay_doctor(participant.doctor, gross, participant.fee_bps)?;
+                self.record_unlocked(Some(participant.doctor), gross);
+            }
+
+            conference.status = ConferenceStatus::Released;
+            self.conferences.insert(conference_id, &conference);
+            for participant in &conference.participants {
+                self.close_case_work(participant.doctor);
+            }
+
+            self.env().emit_event(ConferencePaymentReleased {
+                conference_id,
+                total_amount: conference.amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn cancel_case_conference(&mut self, conference_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut conference = self.conferences
+                .get(conference_id)
+                .ok_or(Error::ConferenceNotFound)?;
+
+            let is_participant = conference.participants.iter().any(|p| p.doctor == caller);
+            if conference.patient != caller && conference.payer != caller && !is_participant {
+                return Err(Error::Unauthorized);
+            }
+
+            if conference.status != ConferenceStatus::Pending
+                || conference.participants.iter().any(|p| p.confirmed)
+            {
+                return Err(Error::CancellationNotAllowed);
+            }
+
+            if self.env().transfer(conference.payer, conference.amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            for (participant, share) in conference.participants.iter().zip(Self::conference_shares(&conference)?) {
+                self.record_refunded(Some(participant.doctor), share);
+                self.record_unlocked(Some(participant.doctor), share);
+            }
+
+            conference.status = ConferenceStatus::Cancelled;
+            self.conferences.insert(conference_id, &conference);
+            for participant in &conference.participants {
+                self.close_case_work(participant.doctor);
+            }
+
+            self.env().emit_event(ConferenceCancelled {
+                conference_id,
+                cancelled_by: caller,
+            });
+
+            Ok(())
+        }
+
+        /// Settles a conference that some invited doctors never confirmed.
+        ///
+        /// Once `confirm_by` has passed, anyone can settle it: doctors who confirmed
+        /// are paid their share and the unconfirmed shares are refunded to the payer.
+        #[ink(message)]
+        pub fn settle_lapsed_conference(&mut self, conference_id: u64) -> Result<()> {
+            let mut conference = self.conferences
+                .get(conference_id)
+                .ok_or(Error::ConferenceNotFound)?;
+
+            if conference.status != ConferenceStatus::Pending {
+                return Err(Error::InvalidStatus);
+            }
+
+            if self.env().block_timestamp() <= conference.confirm_by {
+                return Err(Error::DeadlineNotReached);
+            }
+
+            let shares = Self::conference_shares(&conference)?;
+            let mut paid_to_doctors: Balance = 0;
+            for (participant, gross) in conference.participants.iter().zip(shares) {
+                if participant.confirmed {
+                    self.pay_doctor(participant.doctor, gross, participant.fee_bps)?;
+                    paid_to_doctors = paid_to_doctors
+                        .checked_add(gross)
+                        .ok_or(Error::Overflow)?;
+                } else {
+                    self.record_refunded(Some(participant.doctor), gross);
+                }
+                self.record_unlocked(Some(participant.doctor), gross);
+            }
+
+            let refunded = conference.amount
+                .checked_sub(paid_to_doctors)
+                .ok_or(Error::Overflow)?;
+            if refunded > 0 && self.env().transfer(conference.payer, refunded).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            conference.status = ConferenceStatus::Lapsed;
+            self.conferences.insert(conference_id, &conference);
+            for participant in &conference.participants {
+                self.close_case_work(participant.doctor);
+            }
+
+            self.env().emit_event(ConferenceLapsed {
+                conference_id,
+                paid_to_doctors,
+                refunded,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_case_conference(&self, conference_id: u64) -> Option<CaseConference> {
+            self.conferences.get(conference_id)
+        }
+
+        /// Gross share of each participant, in order; the last one also
+        /// receives the rounding remainder.
+        fn conference_shares(conference: &CaseConference) -> Result<Vec<Balance>> {
+            let mut remaining = conference.amount;
+            let last_index = conference.participants.len() - 1;
+            let mut shares = Vec::with_capacity(conference.participants.len());
+            for (index, participant) in conference.participants.iter().enumerate() {
+                let gross = if index == last_index {
+                    remaining
+                } else {
+                    conference.amount
+                        .checked_mul(Balance::from(participant.share_bps))
+                        .ok_or(Error::Overflow)?
+                        .checked_div(Balance::from(BPS_DENOMINATOR))
+                        .ok_or(Error::Overflow)?
+                };
+                remaining = remaining.checked_sub(gross).ok_or(Error::Overflow)?;
+                shares.push(gross);
+            }
+            Ok(shares)
+        }
+
+        #[ink(message, payable)]
+        pub fn submit_async_case(&mut self, specialty_code: u16, case_ipfs_hash: String) -> Result<u64> {
+            let patient = self.env().caller();
+            let amount = self.env().transferred_value();
+
+            if amount == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+
+            let case_id = self.next_async_case_id;
+            let case = AsyncCase {
+                id: case_id,
+                patient,
+                specialty_code,
+                case_ipfs_hash,
+                amount,
+                status: AsyncCaseStatus::Open,
+                doctor: None,
+                fee_bps: self.specialty_fee_bps.get(specialty_code).unwrap_or(self.platform_fee_bps),
+                submitted_at: self.env().block_timestamp(),
+                claimed_at: None,
+                responded_at: None,
+                response_ipfs_hash: None,
+            };
+            self.async_cases.insert(case_id, &case);
+            self.record_locked(None, amount);
+
+            self.next_async_case_id = self.next_async_case_id
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            self.env().emit_event(AsyncCaseSubmitted {
+                case_id,
+                specialty_code,
+                patient,
+                amount,
+            });
+
+            Ok(case_id)
+        }
+
+        #[ink(message)]
+        pub fn claim_async_case(&mut self, case_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut case = self.async_cases
+                .get(case_id)
+                .ok_or(Error::AsyncCaseNotFound)?;
+
+            if case.status != AsyncCaseStatus::Open {
+                return Err(Error::InvalidStatus);
+            }
+
+            let class = self.doctor_classes.get(caller).ok_or(Error::DoctorNotVerified)?;
+            if class.specialty_code != case.specialty_code {
+                return Err(Error::SpecialtyMismatch);
+            }
+
+            let current_time = self.env().block_timestamp();
+            if current_time > case.submitted_at.saturating_add(self.async_sla.claim_timeout) {
+                return Err(Error::ResponseDeadlinePassed);
+            }
+
+            case.status = AsyncCaseStatus::Claimed;
+            case.doctor = Some(caller);
+            case.claimed_at = Some(current_time);
+            self.async_cases.insert(case_id, &case);
+            self.record_unlocked(None, case.amount);
+            self.record_locked(Some(caller), case.amount);
+            self.open_case_work(caller);
+
+            self.env().emit_event(AsyncCaseClaimed {
+                case_id,
+                doctor: caller,
+                respond_by: current_time.saturating_add(self.async_sla.response_time),
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn respond_async_case(&mut self, case_id: u64, response_ipfs_hash: String) -> Result<()> {
+            let caller = self.env().caller();
+            let mut case = self.async_cases
+                .get(case_id)
+                .ok_or(Error::AsyncCaseNotFound)?;
+
+            if case.doctor != Some(caller) {
+                return Err(Error::Unauthorized);
+            }
+
+            if case.status != AsyncCaseStatus::Claimed {
+                return Err(Error::InvalidStatus);
+            }
+
+            let current_time = self.env().block_timestamp();
+            let claimed_at = case.claimed_at.ok_or(Error::InvalidStatus)?;
+            if current_time > claimed_at.saturating_add(self.async_sla.response_time) {
+                return Err(Error::ResponseDeadlinePassed);
+            }
+
+            case.status = AsyncCaseStatus::Answered;
+            case.responded_at = Some(current_time);
+            case.response_ipfs_hash = Some(response_ipfs_hash);
+            self.async_cases.insert(case_id, &case);
+
+            self.env().emit_event(AsyncCaseAnswered {
+                case_id,
+                doctor: caller,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn release_async_payment(&mut self, case_id: u64) -> Result<()> {
+            let mut case = self.async_cases
+                .get(case_id)
+                .ok_or(Error::AsyncCaseNotFound)?;
+
+            if case.status != AsyncCaseStatus::Answered {
+                return Err(Error::InvalidStatus);
+            }
+
+            let responded_at = case.responded_at.ok_or(Error::InvalidStatus)?;
+            if self.env().block_timestamp().saturating_sub(responded_at) < DISPUTE_WINDOW_MS
+                && self.env().caller() != self.owner
+            {
+                return Err(Error::TooEarlyToRelease);
+            }
+
+            let doctor = case.doctor.ok_or(Error::InvalidStatus)?;
+            let doctor_amount = self.pay_doctor(doctor, case.amount, case.fee_bps)?;
+            self.record_unlocked(Some(doctor), case.amount);
+
+            case.status = AsyncCaseStatus::Released;
+            self.async_cases.insert(case_id, &case);
+            self.close_case_work(doctor);
+
+            self.env().emit_event(AsyncCaseSettled {
+                case_id,
+                status: AsyncCaseStatus::Released,
+                amount: doctor_amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn dispute_async_case(&mut self, case_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut case = self.async_cases
+                .get(case_id)
+                .ok_or(Error::AsyncCaseNotFound)?;
+
+            if case.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if case.status != AsyncCaseStatus::Answered {
+                return Err(Error::InvalidStatus);
+            }
+
+            let responded_at = case.responded_at.ok_or(Error::InvalidStatus)?;
+            if self.env().block_timestamp().saturating_sub(responded_at) > DISPUTE_WINDOW_MS {
+                return Err(Error::DisputeWindowExpired);
+            }
+
+            case.status = AsyncCaseStatus::Disputed;
+            self.async_cases.insert(case_id, &case);
+
+            self.env().emit_event(AsyncCaseDisputed { case_id });
+
+            Ok(())
+        }
+
+        /// Owner resolution of a disputed case: either refund the patient in
+        /// full or pay the doctor as a normal release would.
+        #[ink(message)]
+        pub fn resolve_async_dispute(&mut self, case_id: u64, refund: bool) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+
+            let mut case = self.async_cases
+                .get(case_id)
+                .ok_or(Error::AsyncCaseNotFound)?;
+
+            if case.status != AsyncCaseStatus::Disputed {
+                return Err(Error::InvalidStatus);
+            }
+
+            let doctor = case.doctor.ok_or(Error::InvalidStatus)?;
+            let amount = if refund {
+                if self.env().transfer(case.patient, case.amount).is_err() {
+                    return Err(Error::TransferFailed);
+                }
+                self.record_refunded(Some(doctor), case.amount);
+                case.status = AsyncCaseStatus::Refunded;
+                case.amount
+            } else {
+                case.status = AsyncCaseStatus::Released;
+                self.pay_doctor(doctor, case.amount, case.fee_bps)?
+            };
+            self.record_unlocked(Some(doctor), case.amount);
+            self.async_cases.insert(case_id, &case);
+            self.close_case_work(doctor);
+
+            self.env().emit_event(AsyncCaseSettled {
+                case_id,
+                status: case.status.clone(),
+                amount,
+            });
+
+            Ok(())
+        }
+
+        /// Refunds a case nobody claimed in time or whose doctor missed the
+        /// response deadline. Anyone can trigger it; the patient may also
+        /// withdraw a case that is still open.
+        #[ink(message)]
+        pub fn refund_async_case(&mut self, case_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut case = self.async_cases
+                .get(case_id)
+                .ok_or(Error::AsyncCaseNotFound)?;
+
+            let current_time = self.env().block_timestamp();
+            let refundable = match case.status {
+                AsyncCaseStatus::Open => caller == case.patient
+                    || current_time > case.submitted_at.saturating_add(self.async_sla.claim_timeout),
+                AsyncCaseStatus::Claimed => {
+                    let claimed_at = case.claimed_at.ok_or(Error::InvalidStatus)?;
+                    current_time > claimed_at.saturating_add(self.async_sla.response_time)
+                }
+                _ => return Err(Error::InvalidStatus),
+            };
+            if !refundable {
+                return Err(Error::DeadlineNotReached);
+            }
+
+            if self.env().transfer(case.patient, case.amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            self.record_refunded(case.doctor, case.amount);
+            self.record_unlocked(case.doctor, case.amount);
+
+            case.status = AsyncCaseStatus::Refunded;
+            self.async_cases.insert(case_id, &case);
+            if let Some(doctor) = case.doctor {
+                self.close_case_work(doctor);
+            }
+
+            self.env().emit_event(AsyncCaseSettled {
+                case_id,
+                status: AsyncCaseStatus::Refunded,
+                amount: case.amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_async_case(&self, case_id: u64) -> Option<AsyncCase> {
+            self.async_cases.get(case_id)
+        }
+
+        #[ink(message)]
+        pub fn update_async_sla(&mut self, sla: AsyncSla) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            if sla.claim_timeout == 0 || sla.response_time == 0 {
+                return Err(Error::InvalidSlaConfig);
+            }
+            self.async_sla = sla;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_async_sla(&self) -> AsyncSla {
+            self.async_sla.clone()
+        }
+
+        #[ink(message, payable)]
+        pub fn join_queue(&mut self, specialty_code: u16) -> Result<u64> {
+            let patient = self.env().caller();
+            let amount = self.env().transferred_value();
+
+            if amount == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+
+            let mut queue = self.specialty_queues.get(specialty_code).unwrap_or_default();
+            if queue.len() >= MAX_QUEUE_LENGTH {
+                return Err(Error::QueueFull);
+            }
+
+            let ticket_id = self.next_ticket_id;
+            let ticket = QueueTicket {
+                id: ticket_id,
+                patient,
+                specialty_code,
+                amount,
+                joined_at: self.env().block_timestamp(),
+                status: TicketStatus::Waiting,
+                consultation_id: None,
+            };
+            self.queue_tickets.insert(ticket_id, &ticket);
+            self.record_locked(None, amount);
+            queue.push(ticket_id);
+            self.specialty_queues.insert(specialty_code, &queue);
+
+            self.next_ticket_id = self.next_ticket_id
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            self.env().emit_event(QueueJoined {
+                ticket_id,
+                specialty_code,
+                patient,
+                position: u32::try_from(queue.len()).unwrap_or(u32::MAX),
+            });
+
+            Ok(ticket_id)
+        }
+
+        #[ink(message)]
+        pub fn set_on_call(&mut self, on_call: bool) -> Result<()> {
+            let caller = self.env().caller();
+
+            if !self.doctor_classes.contains(caller) {
+                return Err(Error::DoctorNotVerified);
+            }
+
+            if on_call {
+                self.on_call_doctors.insert(caller, &true);
+            } else {
+                self.on_call_doctors.remove(caller);
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn is_on_call(&self, doctor: AccountId) -> bool {
+            self.on_call_doctors.get(doctor).unwrap_or(false)
+        }
+
+        #[ink(message)]
+        pub fn pull_next_case(&mut self, specialty_code: u16) -> Result<u64> {
+            let doctor = self.env().caller();
+
+            if !self.is_on_call(doctor) {
+                return Err(Error::NotOnCall);
+            }
+
+            let class = self.doctor_classes.get(doctor).ok_or(Error::DoctorNotVerified)?;
+            if class.specialty_code != specialty_code {
+                return Err(Error::SpecialtyMismatch);
+            }
+
+            let mut queue = self.specialty_queues.get(specialty_code).unwrap_or_default();
+            if queue.is_empty() {
+                return Err(Error::QueueEmpty);
+            }
+
+            // The queue fee leaves no room for a reliability deposit, so patients
+            // who owe this doctor one stay queued for the other doctors on call.
+            let position = queue
+                .iter()
+                .position(|ticket_id| {
+                    self.queue_tickets
+                        .get(ticket_id)
+                        .is_some_and(|ticket| self.get_required_deposit(doctor, ticket.patient) == 0)
+                })
+                .ok_or(Error::NoAssignableTicket)?;
+            let ticket_id = queue.remove(position);
+            self.specialty_queues.insert(specialty_code, &queue);
+
+            let mut ticket = self.queue_tickets
+                .get(ticket_id)
+                .ok_or(Error::TicketNotFound)?;
+
+            let current_time = self.env().block_timestamp();
+            self.record_unlocked(None, ticket.amount);
+            let consultation_id = self.create_consultation(
+                ticket.patient,
+                ticket.patient,
+                doctor,
+                current_time,
+                ticket.amount,
+                ticket.amount,
+            )?;
+
+            ticket.status = TicketStatus::Assigned;
+            ticket.consultation_id = Some(consultation_id);
+            self.queue_tickets.insert(ticket_id, &ticket);
+
+            self.env().emit_event(QueueCasePulled {
+                ticket_id,
+                doctor,
+                consultation_id,
+            });
+
+            Ok(consultation_id)
+        }
+
+        #[ink(message)]
+        pub fn leave_queue(&mut self, ticket_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let mut ticket = self.queue_tickets
+                .get(ticket_id)
+                .ok_or(Error::TicketNotFound)?;
+
+            if ticket.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if ticket.status != TicketStatus::Waiting {
+                return Err(Error::InvalidStatus);
+            }
+
+            if self.env().block_timestamp() <= ticket.joined_at.saturating_add(self.queue_timeout) {
+                return Err(Error::DeadlineNotReached);
+            }
+
+            if self.env().transfer(ticket.patient, ticket.amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            self.record_refunded(None, ticket.amount);
+            self.record_unlocked(None, ticket.amount);
+
+            let mut queue = self.specialty_queues.get(ticket.specialty_code).unwrap_or_default();
+            queue.retain(|id| *id != ticket_id);
+            self.specialty_queues.insert(ticket.specialty_code, &queue);
+
+            ticket.status = TicketStatus::Left;
+            self.queue_tickets.insert(ticket_id, &ticket);
+
+            self.env().emit_event(QueueLeft {
+                ticket_id,
+                refund_amount: ticket.amount,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_queue_ticket(&self, ticket_id: u64) -> Option<QueueTicket> {
+            self.queue_tickets.get(ticket_id)
+        }
+
+        #[ink(message)]
+        pub fn get_queue_length(&self, specialty_code: u16) -> u32 {
+            let queue = self.specialty_queues.get(specialty_code).unwrap_or_default();
+            u32::try_from(queue.len()).unwrap_or(u32::MAX)
+        }
+
+        #[ink(message)]
+        pub fn update_queue_timeout(&mut self, queue_timeout: u64) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            self.queue_timeout = queue_timeout;
+            Ok(())
+        }
+
+        #[ink(message, payable)]
+        pub fn join_waitlist(&mut self, doctor: AccountId) -> Result<()> {
+            let patient = self.env().caller();
+            let deposit = self.env().transferred_value();
+
+            let mut waitlist = self.waitlists.get(doctor).unwrap_or_default();
+            if waitlist.iter().any(|entry| entry.patient == patient) {
+                return Err(Error::AlreadyWaitlisted);
+            }
+            if waitlist.len() >= MAX_WAITLIST_LENGTH {
+                return Err(Error::WaitlistFull);
+            }
+            if self.has_free_slot(doctor) {
+                return Err(Error::FreeSlotsAvailable);
+            }
+
+            waitlist.push(WaitlistEntry {
+                patient,
+                deposit,
+                joined_at: self.env().block_timestamp(),
+            });
+            self.waitlists.insert(doctor, &waitlist);
+
+            self.env().emit_event(WaitlistJoined {
+                doctor,
+                patient,
+                deposit,
+                position: u32::try_from(waitlist.len()).unwrap_or(u32::MAX),
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn leave_waitlist(&mut self, doctor: AccountId) -> Result<()> {
+            let patient = self.env().caller();
+            let mut waitlist = self.waitlists.get(doctor).unwrap_or_default();
+
+            let position = waitlist
+                .iter()
+                .position(|entry| entry.patient == patient)
+                .ok_or(Error::NotWaitlisted)?;
+            let entry = waitlist.remove(position);
+
+            if entry.deposit > 0 && self.env().transfer(patient, entry.deposit).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            self.waitlists.insert(doctor, &waitlist);
+            Ok(())
+        }
+
+        /// Lets a doctor hand a newly published slot to their waitlist.
+        #[ink(message)]
+        pub fn announce_slot(&mut self, slot_time: u64) -> Result<()> {
+            let doctor = self.env().caller();
+
+            if slot_time <= self.env().block_timestamp() {
+                return Err(Error::InvalidScheduleTime);
+            }
+
+            if !self.offer_freed_slot(doctor, slot_time) {
+                return Err(Error::NotWaitlisted);
+            }
+            Ok(())
+        }
+
+        /// Drops an offer the patient let lapse, returning their deposit, and
+        /// passes the slot on to the next patient in line.
+        #[ink(message)]
+        pub fn expire_slot_offer(&mut self, doctor: AccountId, slot_time: u64) -> Result<()> {
+            let offer = self.slot_offers
+                .get((doctor, slot_time))
+                .ok_or(Error::NoSlotOffer)?;
+
+            if self.env().block_timestamp() <= offer.expires_at {
+                return Err(Error::OfferStillActive);
+            }
+
+            if offer.deposit > 0 && self.env().transfer(offer.patient, offer.deposit).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            self.slot_offers.remove((doctor, slot_time));
+            if slot_time > self.env().block_timestamp() && !self.is_slot_booked(doctor, slot_time) {
+                self.offer_freed_slot(doctor, slot_time);
+            }
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_waitlist(&self, doctor: AccountId) -> Vec<WaitlistEntry> {
+            self.waitlists.get(doctor).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn get_slot_offer(&self, doctor: AccountId, slot_time: u64) -> Option<SlotOffer> {
+            self.slot_offers.get((doctor, slot_time))
+        }
+
+        #[ink(message)]
+        pub fn update_slot_offer_window(&mut self, window: u64) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            self.slot_offer_window = window;
+            Ok(())
+        }
+
+        /// Whether an active consultation already occupies the doctor's slot.
+        fn is_slot_booked(&self, doctor: AccountId, slot_time: u64) -> bool {
+            self.booked_slots.contains((doctor, slot_time))
+        }
+
+        /// Whether the doctor publishes a future slot in the registry that is
+        /// neither booked nor held for a waitlisted patient. Without a registry
+        /// there is no availability to check against.
+        fn has_free_slot(&self, doctor: AccountId) -> bool {
+            if !self.registry_configured() {
+                return false;
+            }
+
+            let result = build_call::<DefaultEnvironment>()
+                .call(self.health_registry_address)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_available_slots")))
+                        .push_arg(doctor)
+                )
+                .returns::<Vec<RegistryTimeSlot>>()
+                .try_invoke();
+            let Ok(Ok(slots)) = result else {
+                return false;
+            };
+
+            let current_time = self.env().block_timestamp();
+            slots.iter().any(|slot| {
+                slot.start_time > current_time
+                    && !slot.is_booked
+                    && self.slot_offers
+                        .get((doctor, slot.start_time))
+                        .is_none_or(|offer| current_time > offer.expires_at)
+                    && !self.is_slot_booked(doctor, slot.start_time)
+            })
+        }
+
+        /// Returns the deposit of a lapsed offer whose slot has just been taken,
+        /// instead of leaving a dead offer behind.
+        fn drop_lapsed_offer(&mut self, doctor: AccountId, slot_time: u64, offer: &SlotOffer) -> Result<()> {
+            if offer.deposit > 0 && self.env().transfer(offer.patient, offer.deposit).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            self.slot_offers.remove((doctor, slot_time));
+            Ok(())
+        }
+
+        /// Gives the first waitlisted patient an exclusive, time-limited right to
+        /// book the slot. Returns whether anyone was waiting.
+        fn offer_freed_slot(&mut self, doctor: AccountId, slot_time: u64) -> bool {
+            let current_time = self.env().block_timestamp();
+            if let Some(offer) = self.slot_offers.get((doctor, slot_time)) {
+                if current_time <= offer.expires_at {
+                    return true;
+                }
+            }
+
+            let mut waitlist = self.waitlists.get(doctor).unwrap_or_default();
+            if waitlist.is_empty() {
+                return false;
+            }
+            let entry = waitlist.remove(0);
+            self.waitlists.insert(doctor, &waitlist);
+
+            let expires_at = current_time.saturating_add(self.slot_offer_window);
+            self.slot_offers.insert((doctor, slot_time), &SlotOffer {
+                patient: entry.patient,
+                deposit: entry.deposit,
+                expires_at,
+            });
+
+            self.env().emit_event(SlotOffered {
+                doctor,
+                patient: entry.patient,
+                slot_time,
+                expires_at,
+            });
+
+            true
+        }
+
+        #[ink(message)]
+        pub fn set_session_duration(&mut self, duration: u64) -> Result<()> {
+            if duration == 0 || duration > MAX_SESSION_DURATION {
+                return Err(Error::InvalidSessionDuration);
+            }
+            self.session_durations.insert(self.env().caller(), &duration);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_session_duration(&self, doctor: AccountId) -> u64 {
+            self.session_durations
+                .get(doctor)
+                .unwrap_or(DEFAULT_SESSION_DURATION)
+        }
+
+        #[ink(message)]
+        pub fn set_overtime_terms(&mut self, terms: Option<OvertimeTerms>) -> Result<()> {
+            let doctor = self.env().caller();
+            match terms {
+                Some(terms) => {
+                    if terms.increment == 0 || terms.rate_per_increment == 0 {
+                        return Err(Error::InvalidOvertimeTerms);
+                    }
+                    self.overtime_terms.insert(doctor, &terms);
+                }
+                None => self.overtime_terms.remove(doctor),
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_overtime_terms(&self, doctor: AccountId) -> Option<OvertimeTerms> {
+            self.overtime_terms.get(doctor)
+        }
+
+        /// Tops up the amount the doctor may charge for running past the booked
+        /// duration. Whatever is not charged is returned at completion.
+        #[ink(message, payable)]
+        pub fn authorize_overtime(&mut self, consultation_id: u64) -> Result<()> {
+            let caller = self.env().caller();
+            let allowance = self.env().transferred_value();
+            let mut consultation = self.consultations
+                .get(consultation_id)
+                .ok_or(Error::ConsultationNotFound)?;
+
+            if caller != consultation.patient && caller != consultation.payer {
+                return Err(Error::Unauthorized);
+            }
+
+            if consultation.status != ConsultationStatus::InProgress {
+                return Err(Error::InvalidStatus);
+            }
+
+            if consultation.overtime_terms.is_none() {
+                return Err(Error::OvertimeNotOffered);
+            }
+
+            if allowance == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+
+            consultation.overtime_allowance = consultation.overtime_allowance
+                .checked_add(allowance)
+                .ok_or(Error::Overflow)?;
+            self.consultations.insert(consultation_id, &consultation);
+
+            Ok(())
+        }
+
+        /// Returns an overtime allowance that was never charged to the payer.
+        fn refund_overtime_allowance(&mut self, consultation: &mut Consultation) -> Result<()> {
+            if consultation.overtime_allowance > 0 {
+                if self.env()
+                    .transfer(consultation.payer, consultation.overtime_allowance)
+                    .is_err()
+                {
+                    return Err(Error::TransferFailed);
+                }
+                consultation.overtime_allowance = 0;
+            }
+            Ok(())
+        }
+
+        /// Charges each started overtime increment against the pre-authorized
+        /// allowance and returns the unused part to the payer.
+        fn settle_overtime(&mut self, consultation: &mut Consultation, ended_at: u64) -> Result<()> {
+            let allowance = consultation.overtime_allowance;
+            if allowance == 0 {
+                return Ok(());
+            }
+
+            let overtime = consultation.started_at
+                .map(|started_at| ended_at.saturating_sub(started_at))
+                .unwrap_or(0)
+                .saturating_sub(consultation.duration);
+
+            let (increments, charged) = match &consultation.overtime_terms {
+                Some(terms) if overtime > 0 => {
+                    let increments = overtime
+                        .checked_add(terms.increment - 1)
+                        .ok_or(Error::Overflow)?
+                        / terms.increment;
+                    let charge = terms.rate_per_increment
+                        .checked_mul(Balance::from(increments))
+                        .ok_or(Error::Overflow)?;
+                    (increments, charge.min(allowance))
+                }
+                _ => (0, 0),
+            };
+            let refunded = allowance
+                .checked_sub(charged)
+                .ok_or(Error::Overflow)?;
+
+            if refunded > 0 && self.env().transfer(consultation.payer, refunded).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            consultation.amount = consultation.amount
+                .checked_add(charged)
+                .ok_or(Error::Overflow)?;
+            consultation.overtime_allowance = 0;
+            consultation.overtime_charged = charged;
+            self.record_locked(Some(consultation.doctor), charged);
+
+            self.env().emit_event(OvertimeCharged {
+                consultation_id: consultation.id,
+                increments,
+                charged,
+                refunded,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_doctor_totals(&self, doctor: AccountId) -> EarningsTotals {
+            self.doctor_totals.get(doctor).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn get_platform_totals(&self) -> EarningsTotals {
+            self.platform_totals.clone()
+        }
+
+        /// Per-day releases and fees for `days` days starting at `from_day`
+        /// (days are counted from the Unix epoch).
+        #[ink(message)]
+        pub fn get_doctor_daily_earnings(
+            &self,
+            doctor: AccountId,
+            from_day: u64,
+            days: u32,
+        ) -> Vec<(u64, DailyEarnings)> {
+            (from_day..from_day.saturating_add(u64::from(days.min(MAX_SUMMARY_DAYS))))
+                .map(|day| (day, self.doctor_daily_earnings.get((doctor, day)).unwrap_or_default()))
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn get_platform_daily_earnings(&self, from_day: u64, days: u32) -> Vec<(u64, DailyEarnings)> {
+            (from_day..from_day.saturating_add(u64::from(days.min(MAX_SUMMARY_DAYS))))
+                .map(|day| (day, self.platform_daily_earnings.get(day).unwrap_or_default()))
+                .collect()
+        }
+
+        // The accounting helpers saturate rather than fail so they can run after
+        // funds have moved without being able to revert a settlement.
+        // `doctor` is `None` for funds not yet assigned to a doctor (open async
+        // cases and queue tickets); those only count towards the platform totals.
+        fn record_locked(&mut self, doctor: Option<AccountId>, amount: Balance) {
+            if let Some(doctor) = doctor {
+                let mut totals = self.doctor_totals.get(doctor).unwrap_or_default();
+                totals.locked = totals.locked.saturating_add(amount);
+                self.doctor_totals.insert(doctor, &totals);
+            }
+            self.platform_totals.locked = self.platform_totals.locked.saturating_add(amount);
+        }
+
+        fn record_unlocked(&mut self, doctor: Option<AccountId>, amount: Balance) {
+            if let Some(doctor) = doctor {
+                let mut totals = self.doctor_totals.get(doctor).unwrap_or_default();
+                totals.locked = totals.locked.saturating_sub(amount);
+                self.doctor_totals.insert(doctor, &totals);
+            }
+            self.platform_totals.locked = self.platform_totals.locked.saturating_sub(amount);
+        }
+
+        fn record_refunded(&mut self, doctor: Option<AccountId>, amount: Balance) {
+            if let Some(doctor) = doctor {
+                let mut totals = self.doctor_totals.get(doctor).unwrap_or_default();
+                totals.refunded = totals.refunded.saturating_add(amount);
+                self.doctor_totals.insert(doctor, &totals);
+            }
+            self.platform_totals.refunded = self.platform_totals.refunded.saturating_add(amount);
+        }
+
+        fn record_released(&mut self, doctor: AccountId, released: Balance, fee: Balance) {
+            let mut totals = self.doctor_totals.get(doctor).unwrap_or_default();
+            totals.released = totals.released.saturating_add(released);
+            totals.fees = totals.fees.saturating_add(fee);
+            self.doctor_totals.insert(doctor, &totals);
+
+            self.platform_totals.released = self.platform_totals.released.saturating_add(released);
+            self.platform_totals.fees = self.platform_totals.fees.saturating_add(fee);
+
+            let day = self.env().block_timestamp() / DAY_MS;
+            let mut daily = self.doctor_daily_earnings.get((doctor, day)).unwrap_or_default();
+            daily.released = daily.released.saturating_add(released);
+            daily.fees = daily.fees.saturating_add(fee);
+            self.doctor_daily_earnings.insert((doctor, day), &daily);
+
+            let mut platform_daily = self.platform_daily_earnings.get(day).unwrap_or_default();
+            platform_daily.released = platform_daily.released.saturating_add(released);
+            platform_daily.fees = platform_daily.fees.saturating_add(fee);
+            self.platform_daily_earnings.insert(day, &platform_daily);
+        }
+
+        fn record_keeper_reward(&mut self, doctor: AccountId, amount: Balance) {
+            let mut totals = self.doctor_totals.get(doctor).unwrap_or_default();
+            totals.keeper_rewards = totals.keeper_rewards.saturating_add(amount);
+            self.doctor_totals.insert(doctor, &totals);
+            self.platform_totals.keeper_rewards = self.platform_totals.keeper_rewards.saturating_add(amount);
+        }
+
+        #[ink(message)]
+        pub fn get_receipt(&self, consultation_id: u64) -> Option<Receipt> {
+            self.receipts.get(consultation_id)
+        }
+
+        #[ink(message)]
+        pub fn get_patient_receipts(&self, patient: AccountId, offset: u32, limit: u32) -> Vec<Receipt> {
+            let count = self.patient_receipt_count.get(patient).unwrap_or(0);
+            let ids = indexed_ids(&self.patient_receipts, patient, page_range(count, offset, limit));
+            ids.filter_map(|id| self.receipts.get(id)).collect()
+        }
+
+        #[ink(message)]
+        pub fn get_doctor_receipts(&self, doctor: AccountId, offset: u32, limit: u32) -> Vec<Receipt> {
+            let count = self.doctor_receipt_count.get(doctor).unwrap_or(0);
+            let ids = indexed_ids(&self.doctor_receipts, doctor, page_range(count, offset, limit));
+            ids.filter_map(|id| self.receipts.get(id)).collect()
+        }
+
+        /// Blake2x256 over the SCALE encoding of the stored receipt.
+        #[ink(message)]
+        pub fn get_receipt_hash(&self, consultation_id: u64) -> Option<[u8; 32]> {
+            self.receipts
+                .get(consultation_id)
+                .map(|receipt| self.receipt_hash(&receipt))
+        }
+
+        #[ink(message)]
+        pub fn verify_receipt(&self, receipt: Receipt, receipt_hash: [u8; 32]) -> bool {
+            self.receipt_hash(&receipt) == receipt_hash
+                && self.receipts.get(receipt.consultation_id) == Some(receipt)
+        }
+
+        fn issue_receipt(&mut self, consultation: &Consultation, receipt_number: u64, net: Balance) -> Result<()> {
+            let receipt = Receipt {
+                receipt_number,
+                consultation_id: consultation.id,
+                payer: consultation.payer,
+                patient: consultation.patient,
+                doctor: consultation.doctor,
+                gross: consultation.amount,
+                patient_paid: Self::patient_paid(consultation),
+                fee: consultation.amount.saturating_sub(net),
+                net,
+                asset: PaymentAsset::Native,
+                specialty_code: self.doctor_classes
+                    .get(consultation.doctor)
+                    .map(|class| class.specialty_code),
+                booked_at: consultation.created_at,
+                scheduled_time: consultation.scheduled_time,
+                completed_at: consultation.completed_at,
+                settled_at: self.env().block_timestamp(),
+            };
+            self.receipts.insert(consultation.id, &receipt);
+
+            push_indexed(
+                &mut self.patient_receipts,
+                &mut self.patient_receipt_count,
+                consultation.patient,
+                consultation.id,
+            )?;
+            push_indexed(
+                &mut self.doctor_receipts,
+                &mut self.doctor_receipt_count,
+                consultation.doctor,
+                consultation.id,
+            )?;
+
+            self.env().emit_event(ReceiptIssued {
+                consultation_id: consultation.id,
+                patient: consultation.patient,
+                receipt_number,
+                receipt_hash: self.receipt_hash(&receipt),
+            });
+
+            Ok(())
+        }
+
+        /// What the patient paid for the booking itself. Subsidies and the
+        /// waitlist deposit are part of `amount` but were not paid at booking,
+        /// and a booking paid by someone else costs the patient nothing.
+        fn patient_paid(consultation: &Consultation) -> Balance {
+            if consultation.payer != consultation.patient {
+                return 0;
+            }
+            consultation.amount
+                .saturating_sub(consultation.subsidy_amount)
+                .saturating_sub(consultation.waitlist_deposit)
+        }
+
+        fn receipt_hash(&self, receipt: &Receipt) -> [u8; 32] {
+            self.env().hash_encoded::<ink::env::hash::Blake2x256, _>(receipt)
+        }
+
+        #[ink(message)]
+        pub fn get_patient_reliability(&self, patient: AccountId) -> PatientReliability {
+            self.patient_reliability.get(patient).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn set_deposit_requirement(&mut self, requirement: Option<DepositRequirement>) -> Result<()> {
+            let doctor = self.env().caller();
+            match requirement {
+                Some(requirement) => {
+                    if requirement.no_show_threshold == 0 || requirement.deposit == 0 {
+                        return Err(Error::InvalidDepositRequirement);
+                    }
+                    self.deposit_requirements.insert(doctor, &requirement);
+                }
+                None => self.deposit_requirements.remove(doctor),
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_deposit_requirement(&self, doctor: AccountId) -> Option<DepositRequirement> {
+            self.deposit_requirements.get(doctor)
+        }
+
+        /// The deposit `patient` has to add on top of the fee when booking `doctor`.
+        #[ink(message)]
+        pub fn get_required_deposit(&self, doctor: AccountId, patient: AccountId) -> Balance {
+            match self.deposit_requirements.get(doctor) {
+                Some(requirement)
+                    if self.get_patient_reliability(patient).no_shows >= requirement.no_show_threshold =>
+                {
+                    requirement.deposit
+                }
+                _ => 0,
+            }
+        }
+
+        fn update_patient_reliability(&mut self, patient: AccountId, update: impl FnOnce(&mut PatientReliability)) {
+            let mut reliability = self.patient_reliability.get(patient).unwrap_or_default();
+            update(&mut reliability);
+            self.patient_reliability.insert(patient, &reliability);
+        }
+
+        /// Pays out a consultation's reliability deposit: to the doctor when the
+        /// patient no-showed, back to the payer otherwise.
+        fn settle_reliability_deposit(&mut self, consultation: &mut Consultation, forfeit: bool) -> Result<()> {
+            let amount = consultation.reliability_deposit;
+            if amount == 0 {
+                return Ok(());
+            }
+
+            let recipient = if forfeit { consultation.doctor } else { consultation.payer };
+            if self.env().transfer(recipient, amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+            consultation.reliability_deposit = 0;
+
+            self.env().emit_event(ReliabilityDepositSettled {
+                consultation_id: consultation.id,
+                recipient,
+                amount,
+                forfeited: forfeit,
+            });
+
+            Ok(())
+        }
+
+        /// Returns `amount` of a consultation's escrow. The subsidized share of it
+        /// goes back to the subsidy pool, the rest to whoever paid.
+        fn refund(&mut self, consultation: &Consultation, amount: Balance) -> Result<()> {
+            let pool_share = if consultation.subsidy_amount > 0 {
+                amount
+                    .checked_mul(consultation.subsidy_amount)
+                    .ok_or(Error::Overflow)?
+                    .checked_div(consultation.amount)
+                    .ok_or(Error::Overflow)?
+            } else {
+                0
+            };
+            let payer_share = amount.checked_sub(pool_share).ok_or(Error::Overflow)?;
+            self.record_refunded(Some(consultation.doctor), amount);
+
+            if payer_share > 0 && self.env().transfer(consultation.payer, payer_share).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            if pool_share > 0 {
+                self.subsidy_pool.balance = self.subsidy_pool.balance
+                    .checked_add(pool_share)
+                    .ok_or(Error::Overflow)?;
+                self.subsidy_pool.total_returned = self.subsidy_pool.total_returned
+                    .checked_add(pool_share)
+                    .ok_or(Error::Overflow)?;
+            }
+
+            Ok(())
+        }
+
+        #[ink(message, payable)]
+        pub fn donate_to_subsidy_pool(&mut self) -> Result<()> {
+            let donor = self.env().caller();
+            let amount = self.env().transferred_value();
+
+            if amount == 0 {
+                return Err(Error::InsufficientPayment);
+            }
+
+            self.subsidy_pool.balance = self.subsidy_pool.balance
+                .checked_add(amount)
+                .ok_or(Error::Overflow)?;
+            self.subsidy_pool.total_donated = self.subsidy_pool.total_donated
+                .checked_add(amount)
+                .ok_or(Error::Overflow)?;
+
+            self.env().emit_event(SubsidyDonated { donor, amount });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_subsidy_approver(&mut self, account: AccountId, approved: bool) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            if approved {
+                self.subsidy_approvers.insert(account, &true);
+            } else {
+                self.subsidy_approvers.remove(account);
+            }
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn set_subsidy_eligibility(&mut self, patient: AccountId, eligible: bool) -> Result<()> {
+            let caller = self.env().caller();
+            if caller != self.owner && !self.subsidy_approvers.get(caller).unwrap_or(false) {
+                return Err(Error::Unauthorized);
+            }
+
+            if eligible {
+                self.subsidy_eligible.insert(patient, &true);
+            } else {
+                self.subsidy_eligible.remove(patient);
+            }
+
+            self.env().emit_event(SubsidyEligibilityUpdated {
+                patient,
+                eligible,
+                updated_by: caller,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn update_subsidy_limits(&mut self, limits: SubsidyLimits) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            if limits.period == 0 || limits.max_per_consultation > limits.max_per_patient_per_period {
+                return Err(Error::InvalidSubsidyLimits);
+            }
+            self.subsidy_limits = limits;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_subsidy_limits(&self) -> SubsidyLimits {
+            self.subsidy_limits.clone()
+        }
+
+        #[ink(message)]
+        pub fn get_subsidy_pool(&self) -> SubsidyPool {
+            self.subsidy_pool.clone()
+        }
+
+        #[ink(message)]
+        pub fn is_subsidy_eligible(&self, patient: AccountId) -> bool {
+            self.subsidy_eligible.get(patient).unwrap_or(false)
+        }
+
+        #[ink(message)]
+        pub fn get_subsidy_used(&self, patient: AccountId) -> Balance {
+            self.subsidy_usage
+                .get(patient)
+                .filter(|usage| usage.period_index == self.current_subsidy_period())
+                .map(|usage| usage.used)
+                .unwrap_or(0)
+        }
+
+        fn current_subsidy_period(&self) -> u64 {
+            self.env()
+                .block_timestamp()
+                .checked_div(self.subsidy_limits.period)
+                .unwrap_or(0)
+        }
+
+        /// Best-effort bump of one of the doctor's reliability counters in the
+        /// health registry. Skipped while no registry is configured; a failed
+        /// update doesn't revert the settlement but is reported in an event.
+        fn notify_registry(&self, selector: [u8; 4], doctor: AccountId) {
+            if !self.registry_configured() {
+                return;
+            }
+
+            let result = build_call::<DefaultEnvironment>()
+                .call(self.health_registry_address)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector)).push_arg(doctor)
+                )
+                .returns::<RegistryResult>()
+                .try_invoke();
+
+            if !matches!(result, Ok(Ok(Ok(())))) {
+                self.env().emit_event(RegistryUpdateFailed { doctor, selector });
+            }
+        }
+
+        /// Best-effort mirror of the doctor's bond into the health registry, where
+        /// it feeds into doctor rankings. Skipped while no registry is configured.
+        fn sync_registry_bond(&self, doctor: AccountId, bond: Balance) {
+            if !self.registry_configured() {
+                return;
+            }
+
+            let selector = ink::selector_bytes!("set_doctor_bond");
+            let result = build_call::<DefaultEnvironment>()
+                .call(self.health_registry_address)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector))
+                        .push_arg(doctor)
+                        .push_arg(bond)
+                )
+                .returns::<RegistryResult>()
+                .try_invoke();
+
+            if !matches!(result, Ok(Ok(Ok(())))) {
+                self.env().emit_event(RegistryUpdateFailed { doctor, selector });
+            }
+        }
+
+        /// Best-effort move of a booking between the doctor's slots in the health
+        /// registry. Times that aren't registry slots are reported in an event
+        /// rather than blocking the reschedule.
+        fn update_registry_slot(&self, selector: [u8; 4], doctor: AccountId, start_time: u64) {
+            if !self.registry_configured() {
+                return;
+            }
+
+            let result = build_call::<DefaultEnvironment>()
+                .call(self.health_registry_address)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(selector))
+                        .push_arg(doctor)
+                        .push_arg(start_time)
+                )
+                .returns::<RegistryResult>()
+                .try_invoke();
+
+            if !matches!(result, Ok(Ok(Ok(())))) {
+                self.env().emit_event(RegistryUpdateFailed { doctor, selector });
+            }
+        }
+
+        fn registry_configured(&self) -> bool {
+            self.health_registry_address != AccountId::from([0x0; 32])
+        }
+    }
+
+    /// Appends `id` to `account`'s entries in a counted index.
+    fn push_indexed<I: StorageKey, C: StorageKey>(
+        index: &mut Mapping<(AccountId, u32), u64, I>,
+        counts: &mut Mapping<AccountId, u32, C>,
+        account: AccountId,
+        id: u64,
+    ) -> Result<()> {
+        let count = counts.get(account).unwrap_or(0);
+        index.insert((account, count), &id);
+        counts.insert(account, &count.checked_add(1).ok_or(Error::Overflow)?);
+        Ok(())
+    }
+
+    /// Reads the ids at `positions` from `account`'s entries in a counted index.
+    fn indexed_ids<I: StorageKey>(
+        index: &Mapping<(AccountId, u32), u64, I>,
+        account: AccountId,
+        positions: core::ops::Range<u32>,
+    ) -> impl Iterator<Item = u64> + '_ {
+        positions.filter_map(move |position| index.get((account, position)))
+    }
+
+    /// Positions of one page of at most `MAX_PAGE_SIZE` entries in an index
+    /// holding `count` of them.
+    fn page_range(count: u32, offset: u32, limit: u32) -> core::ops::Range<u32> {
+        let start = offset.min(count);
+        start..start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(count)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[ink::test]
+        fn new_works() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let contract = ConsultationEscrow::new(accounts.bob, 300, accounts.alice);
+            assert_eq!(contract.get_platform_fee_bps(), 300);
+        }
+
+        #[ink::test]
+        fn book_consultation_works() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, accounts.alice);
+            
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000000000000);
+            
+            let result = contract.book_consultation(accounts.charlie, 1234567890);
+            assert!(result.is_ok());
+            
+            let consultation_id = result.unwrap();
+            assert_eq!(consultation_id, 1);
+            
+            let consultation = contract.get_consultation(consultation_id);
+            assert!(consultation.is_some());
+            assert_eq!(consultation.unwrap().status, ConsultationStatus::Pending);
+        }
+
+        #[ink::test]
+        fn complete_flow_works() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, accounts.alice);
+            
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000000000000);
+            let consultation_id = contract.book_consultation(accounts.charlie, 1234567890).unwrap();
+            
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert!(contract.start_consultation(consultation_id).is_ok());
+            
+            assert!(contract.mark_completed(consultation_id, "QmNotes123".to_string()).is_ok());
+            
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!(consultation.status, ConsultationStatus::Completed);
+        }
+
+        #[ink::test]
+        fn unauthorized_access_fails() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, accounts.alice);
+            
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000000000000);
+            let consultation_id = contract.book_consultation(accounts.charlie, 1234567890).unwrap();
+            
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            let result = contract.start_consultation(consultation_id);
+            assert_eq!(result, Err(Error::Unauthorized));
+        }
+
+        #[ink::test]
+        fn doctor_cancellation_refunds_in_full_and_forfeits_bond() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_doctor_cancellation_penalty(300).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(500);
+            contract.deposit_bond().unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.charlie, 1_000).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert!(contract.cancel_consultation(consultation_id).is_ok());
+
+            let patient_balance = ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(patient_balance, Ok(1300));
+            assert_eq!(contract.get_doctor_bond(accounts.charlie), 200);
+            assert_eq!(
+                contract.get_consultation(consultation_id).unwrap().status,
+                ConsultationStatus::Cancelled
+            );
+        }
+
+        #[ink::test]
+        fn cancellation_uses_policy_snapshotted_at_booking() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.set_cancellation_policy(ink::prelude::vec![
+                RefundTier { hours_before: 48, refund_percent: 100 },
+                RefundTier { hours_before: 0, refund_percent: 20 },
+            ]).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.charlie, 10 * HOUR_MS).unwrap();
+
+            // Later policy edits must not affect the existing booking.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.set_cancellation_policy(ink::prelude::vec![
+                RefundTier { hours_before: 0, refund_percent: 100 },
+            ]).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert!(contract.cancel_consultation(consultation_id).is_ok());
+
+            let patient_balance = ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(patient_balance, Ok(200));
+        }
+
+        #[ink::test]
+        fn invalid_cancellation_policy_is_rejected() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, accounts.alice);
+
+            let result = contract.set_cancellation_policy(ink::prelude::vec![
+                RefundTier { hours_before: 0, refund_percent: 50 },
+                RefundTier { hours_before: 24, refund_percent: 100 },
+            ]);
+            assert_eq!(result, Err(Error::InvalidCancellationPolicy));
+
+            let result = contract.set_cancellation_policy(ink::prelude::vec![
+                RefundTier { hours_before: 48, refund_percent: 100 },
+            ]);
+            assert_eq!(result, Err(Error::InvalidCancellationPolicy));
+        }
+
+        #[ink::test]
+        fn reschedule_requires_consent_from_other_party() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.charlie, 10 * HOUR_MS).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(10 * HOUR_MS - 1);
+            contract.check_in(consultation_id).unwrap();
+            contract.propose_reschedule(consultation_id, 20 * HOUR_MS).unwrap();
+            assert_eq!(contract.accept_reschedule(consultation_id), Err(Error::Unauthorized));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            assert!(contract.accept_reschedule(consultation_id).is_ok());
+
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!(consultation.scheduled_time, 20 * HOUR_MS);
+            assert_eq!(consultation.reschedule_count, 1);
+            assert_eq!(consultation.patient_checked_in_at, None);
+            assert!(contract.get_reschedule_proposal(consultation_id).is_none());
+        }
+
+        #[ink::test]
+        fn patient_no_show_pays_doctor_configured_share() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.update_no_show_settings(HOUR_MS, 40).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.eve, 10 * HOUR_MS).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(10 * HOUR_MS + 1);
+            contract.check_in(consultation_id).unwrap();
+            assert_eq!(contract.report_patient_no_show(consultation_id), Err(Error::TooEarlyToRelease));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(11 * HOUR_MS + 1);
+            assert!(contract.report_patient_no_show(consultation_id).is_ok());
+
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.eve),
+                Ok(400)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(600)
+            );
+            assert_eq!(
+                contract.get_consultation(consultation_id).unwrap().status,
+                ConsultationStatus::PatientNoShow
+            );
+        }
+
+        #[ink::test]
+        fn no_show_claim_rejected_when_doctor_checked_in() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.eve, 10 * HOUR_MS).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(10 * HOUR_MS);
+            contract.check_in(consultation_id).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.check_in(consultation_id).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(12 * HOUR_MS);
+            assert_eq!(contract.report_patient_no_show(consultation_id), Err(Error::NoShowClaimRejected));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.report_no_show(consultation_id), Err(Error::NoShowClaimRejected));
+        }
+
+        #[ink::test]
+        fn expire_consultation_refunds_and_rewards_keeper() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.update_sla_config(SlaConfig {
+                start_deadline: 2 * HOUR_MS,
+                completion_deadline: 24 * HOUR_MS,
+                in_progress_doctor_percent: 50,
+                keeper_reward: 10,
+            }).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let pending_id = contract.book_consultation(accounts.eve, 10 * HOUR_MS).unwrap();
+            let started_id = contract.book_consultation(accounts.eve, 10 * HOUR_MS).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            contract.deposit_bond().unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(10 * HOUR_MS);
+            contract.start_consultation(started_id).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(11 * HOUR_MS);
+            assert_eq!(contract.expire_consultation(pending_id), Err(Error::DeadlineNotReached));
+
+            // A session that never started is refunded in full; the reward comes
+            // out of the doctor's bond.
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(12 * HOUR_MS + 1);
+            assert!(contract.expire_consultation(pending_id).is_ok());
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(10)
+            );
+            assert_eq!(contract.get_doctor_bond(accounts.eve), 90);
+            assert_eq!(contract.get_platform_totals().keeper_rewards, 0);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(1000)
+            );
+            assert_eq!(
+                contract.get_consultation(pending_id).unwrap().status,
+                ConsultationStatus::Expired
+            );
+
+            // The keeper reward comes out of the doctor's share, which then pays the fee.
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(34 * HOUR_MS + 1);
+            assert!(contract.expire_consultation(started_id).is_ok());
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(20)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(1500)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.eve),
+                Ok(476)
+            );
+            assert_eq!(contract.get_accrued_fees(accounts.bob), 14);
+            let totals = contract.get_doctor_totals(accounts.eve);
+            assert_eq!((totals.keeper_rewards, totals.fees), (10, 14));
+        }
+
+        #[ink::test]
+        fn release_batch_reports_per_item_results() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let completed_id = contract.book_consultation(accounts.eve, 0).unwrap();
+            let pending_id = contract.book_consultation(accounts.eve, 0).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(completed_id).unwrap();
+            contract.mark_completed(completed_id, "QmNotes".to_string()).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(25 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            let results = contract
+                .release_batch(ink::prelude::vec![completed_id, pending_id, 99])
+                .unwrap();
+
+            assert_eq!(results, ink::prelude::vec![
+                (completed_id, Ok(970)),
+                (pending_id, Err(Error::InvalidStatus)),
+                (99, Err(Error::ConsultationNotFound)),
+            ]);
+            assert_eq!(
+                contract.get_consultation(completed_id).unwrap().status,
+                ConsultationStatus::Released
+            );
+        }
+
+        #[ink::test]
+        fn fee_is_snapshotted_at_booking() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 7, tier: 2 })).unwrap();
+            contract.set_specialty_fee(7, Some(250)).unwrap();
+            assert_eq!(contract.get_fee_bps_for(accounts.eve), 250);
+            contract.set_tier_fee(2, Some(125)).unwrap();
+            assert_eq!(contract.get_fee_bps_for(accounts.eve), 125);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.eve, 0).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.set_tier_fee(2, None).unwrap();
+            contract.update_platform_fee(500).unwrap();
+
+            assert_eq!(contract.get_consultation(consultation_id).unwrap().fee_bps, 125);
+            assert_eq!(contract.update_platform_fee(10_001), Err(Error::InvalidFee));
+        }
+
+        #[ink::test]
+        fn release_splits_fee_across_beneficiaries() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 1000, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_fee_beneficiaries(ink::prelude::vec![
+                FeeBeneficiary { account: accounts.bob, weight: 3 },
+                FeeBeneficiary { account: accounts.frank, weight: 1 },
+            ]).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1010);
+            let consultation_id = contract.book_consultation(accounts.eve, 0).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(consultation_id).unwrap();
+            contract.mark_completed(consultation_id, "QmNotes".to_string()).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(25 * HOUR_MS);
+            contract.release_payment(consultation_id).unwrap();
+
+            // 10% of 1010 is 101: 75 + 25 by weight, plus 1 of dust to the first beneficiary.
+            assert_eq!(contract.get_accrued_fees(accounts.bob), 76);
+            assert_eq!(contract.get_accrued_fees(accounts.frank), 25);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(contract.withdraw_fees(), Ok(25));
+            assert_eq!(contract.withdraw_fees(), Err(Error::NothingToWithdraw));
+        }
+
+        #[ink::test]
+        fn sponsored_booking_refunds_payer_and_keeps_patient_rights() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract
+                .book_consultation_for(accounts.django, accounts.eve, 0)
+                .unwrap();
+
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!(consultation.patient, accounts.django);
+            assert_eq!(consultation.payer, accounts.frank);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(consultation_id).unwrap();
+            contract.mark_completed(consultation_id, "QmNotes".to_string()).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(contract.dispute_consultation(consultation_id), Err(Error::Unauthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.dispute_consultation(consultation_id).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.refund_consultation(consultation_id).unwrap();
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(1000)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(0)
+            );
+        }
+
+        #[ink::test]
+        fn subsidized_booking_draws_capped_amount_from_pool() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            contract.update_subsidy_limits(SubsidyLimits {
+                max_per_consultation: 400,
+                max_per_patient_per_period: 600,
+                period: 30 * 24 * HOUR_MS,
+            }).unwrap();
+            contract.set_subsidy_approver(accounts.charlie, true).unwrap();
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(5000);
+            contract.donate_to_subsidy_pool().unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            contract.set_subsidy_eligibility(accounts.django, true).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            let first = contract.book_subsidized_consultation(accounts.eve, 0, 900).unwrap();
+            let second = contract.book_subsidized_consultation(accounts.eve, 0, 900).unwrap();
+
+            assert_eq!(contract.get_consultation(first).unwrap().amount, 500);
+            assert_eq!(contract.get_consultation(second).unwrap().subsidy_amount, 200);
+            assert_eq!(contract.get_subsidy_used(accounts.django), 600);
+
+            let pool = contract.get_subsidy_pool();
+            assert_eq!(pool.balance, 4400);
+            assert_eq!(pool.total_disbursed, 600);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                contract.book_subsidized_consultation(accounts.eve, 0, 100),
+                Err(Error::NotEligibleForSubsidy)
+            );
+
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(first).unwrap();
+            contract.mark_completed(first, "QmNotes".to_string()).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(25 * HOUR_MS);
+            contract.release_payment(first).unwrap();
+            let receipt = contract.get_receipt(first).unwrap();
+            assert_eq!((receipt.gross, receipt.patient_paid), (500, 100));
+        }
+
+        #[ink::test]
+        fn package_credits_are_consumed_and_refunded_pro_rata() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_package_offer(3, Some(900)).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(800);
+            assert_eq!(contract.purchase_package(accounts.eve, 3), Err(Error::IncorrectPayment));
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(900);
+            let package_id = contract.purchase_package(accounts.eve, 3).unwrap();
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(900);
+            assert_eq!(contract.book_with_package(package_id, 0), Err(Error::IncorrectPayment));
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            let consultation_id = contract.book_with_package(package_id, 0).unwrap();
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!(consultation.amount, 300);
+            assert_eq!(consultation.package_id, Some(package_id));
+
+            contract.cancel_package(package_id).unwrap();
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(600)
+            );
+            assert_eq!(contract.get_package(package_id).unwrap().status, PackageStatus::Cancelled);
+            assert_eq!(contract.book_with_package(package_id, 0), Err(Error::PackageNotActive));
+        }
+
+        #[ink::test]
+        fn subscription_releases_periods_and_refunds_pro_rata() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 0, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            let plan_id = contract.create_subscription_plan(1000, 100, "QmTerms".to_string()).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let subscription_id = contract.subscribe(plan_id).unwrap();
+            contract.renew_subscription(subscription_id).unwrap();
+
+            assert_eq!(contract.release_subscription_periods(subscription_id), Err(Error::NothingToRelease));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(125);
+            assert_eq!(contract.release_subscription_periods(subscription_id), Ok(1000));
+
+            contract.cancel_subscription(subscription_id).unwrap();
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.eve),
+                Ok(1250)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(750)
+            );
+            assert_eq!(
+                contract.get_subscription(subscription_id).unwrap().status,
+                SubscriptionStatus::Cancelled
+            );
+        }
+
+        #[ink::test]
+        fn follow_up_is_linked_and_discounted() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_follow_up_policy(FollowUpPolicy { window: 48 * HOUR_MS, discount_percent: 50 }).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let parent_id = contract.book_consultation(accounts.eve, 0).unwrap();
+            assert_eq!(contract.book_follow_up(parent_id, HOUR_MS), Err(Error::InvalidStatus));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(parent_id).unwrap();
+            contract.mark_completed(parent_id, "QmNotes".to_string()).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.book_follow_up(parent_id, HOUR_MS), Err(Error::IncorrectPayment));
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(500);
+            let follow_up_id = contract.book_follow_up(parent_id, HOUR_MS).unwrap();
+
+            assert_eq!(contract.get_consultation(follow_up_id).unwrap().parent_id, Some(parent_id));
+            assert_eq!(contract.get_follow_up_chain(follow_up_id), ink::prelude::vec![parent_id, follow_up_id]);
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(49 * HOUR_MS);
+            assert_eq!(contract.book_follow_up(parent_id, 50 * HOUR_MS), Err(Error::FollowUpWindowExpired));
+        }
+
+        #[ink::test]
+        fn free_follow_up_needs_no_payment() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_follow_up_policy(FollowUpPolicy { window: 48 * HOUR_MS, discount_percent: 100 }).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let parent_id = contract.book_consultation(accounts.eve, 0).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(parent_id).unwrap();
+            contract.mark_completed(parent_id, "QmNotes".to_string()).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            let follow_up_id = contract.book_follow_up(parent_id, HOUR_MS).unwrap();
+            assert_eq!(contract.get_consultation(follow_up_id).unwrap().amount, 0);
+
+            // Outside the follow-up flow a booking still has to carry a fee.
+            assert_eq!(contract.book_consultation(accounts.eve, HOUR_MS), Err(Error::InsufficientPayment));
+        }
+
+        #[ink::test]
+        fn case_conference_pays_each_doctor_their_share() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 0, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            assert_eq!(
+                contract.book_case_conference(
+                    accounts.django,
+                    ink::prelude::vec![(accounts.eve, 6000), (accounts.frank, 3000)],
+                    0,
+                ),
+                Err(Error::InvalidParticipants)
+            );
+            let conference_id = contract.book_case_conference(
+                accounts.django,
+                ink::prelude::vec![(accounts.eve, 6000), (accounts.frank, 4000)],
+                0,
+            ).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.confirm_conference_completion(conference_id).unwrap();
+            assert_eq!(
+                contract.get_case_conference(conference_id).unwrap().status,
+                ConferenceStatus::Pending
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            contract.confirm_conference_completion(conference_id).unwrap();
+            assert_eq!(contract.release_conference_payment(conference_id), Err(Error::TooEarlyToRelease));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(24 * HOUR_MS);
+            contract.release_conference_payment(conference_id).unwrap();
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.eve),
+                Ok(600)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(400)
+            );
+        }
+
+        #[ink::test]
+        fn unconfirmed_conference_settles_after_deadline() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 0, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let conference_id = contract.book_case_conference(
+                accounts.django,
+                ink::prelude::vec![(accounts.eve, 6000), (accounts.frank, 4000)],
+                0,
+            ).unwrap();
+
+            // Eve confirms, Frank never does: the conference can neither be
+            // cancelled nor released.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.confirm_conference_completion(conference_id).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.cancel_case_conference(conference_id), Err(Error::CancellationNotAllowed));
+            assert_eq!(contract.release_conference_payment(conference_id), Err(Error::InvalidStatus));
+            assert_eq!(contract.settle_lapsed_conference(conference_id), Err(Error::DeadlineNotReached));
+
+            // A confirmed doctor doesn't depend on the patient to get paid.
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(CONFERENCE_CONFIRMATION_WINDOW + 1);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.settle_lapsed_conference(conference_id).unwrap();
+            assert_eq!(
+                contract.get_case_conference(conference_id).unwrap().status,
+                ConferenceStatus::Lapsed
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.eve),
+                Ok(600)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(0)
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(400)
+            );
+            assert_eq!(contract.settle_lapsed_conference(conference_id), Err(Error::InvalidStatus));
+        }
+
+        #[ink::test]
+        fn async_case_refunds_when_response_is_late() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 4, tier: 1 })).unwrap();
+            contract.set_doctor_class(accounts.frank, Some(DoctorClass { specialty_code: 9, tier: 1 })).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let case_id = contract.submit_async_case(4, "QmCase".to_string()).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(contract.claim_async_case(case_id), Err(Error::SpecialtyMismatch));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.claim_async_case(case_id).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(contract.refund_async_case(case_id), Err(Error::DeadlineNotReached));
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(48 * HOUR_MS + 1);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(
+                contract.respond_async_case(case_id, "QmAnswer".to_string()),
+                Err(Error::ResponseDeadlinePassed)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            contract.refund_async_case(case_id).unwrap();
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(1000)
+            );
+            assert_eq!(contract.get_async_case(case_id).unwrap().status, AsyncCaseStatus::Refunded);
+        }
+
+        #[ink::test]
+        fn disputed_async_case_is_resolved_by_owner() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 4, tier: 1 })).unwrap();
+            contract.set_specialty_fee(4, Some(200)).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let case_id = contract.submit_async_case(4, "QmCase".to_string()).unwrap();
+
+            // A fee change after submission does not apply to the case.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.set_specialty_fee(4, Some(500)).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.claim_async_case(case_id).unwrap();
+            assert_eq!(contract.get_async_case(case_id).unwrap().fee_bps, 200);
+            contract.respond_async_case(case_id, "QmAnswer".to_string()).unwrap();
+
+            assert_eq!(contract.dispute_async_case(case_id), Err(Error::Unauthorized));
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.dispute_async_case(case_id).unwrap();
+            assert_eq!(contract.get_async_case(case_id).unwrap().status, AsyncCaseStatus::Disputed);
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(DISPUTE_WINDOW_MS + 1);
+            assert_eq!(contract.release_async_payment(case_id), Err(Error::InvalidStatus));
+            assert_eq!(contract.resolve_async_dispute(case_id, false), Err(Error::Unauthorized));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.resolve_async_dispute(case_id, false).unwrap();
+            assert_eq!(contract.get_async_case(case_id).unwrap().status, AsyncCaseStatus::Released);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.eve),
+                Ok(980)
+            );
+            assert_eq!(contract.resolve_async_dispute(case_id, true), Err(Error::InvalidStatus));
+        }
+
+        #[ink::test]
+        fn on_call_doctor_pulls_oldest_queued_case() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 4, tier: 1 })).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let first = contract.join_queue(4).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            let second = contract.join_queue(4).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(contract.pull_next_case(4), Err(Error::NotOnCall));
+            contract.set_on_call(true).unwrap();
+            let consultation_id = contract.pull_next_case(4).unwrap();
+
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!(consultation.patient, accounts.django);
+            assert_eq!(contract.get_queue_ticket(first).unwrap().status, TicketStatus::Assigned);
+            assert_eq!(contract.get_queue_length(4), 1);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(contract.leave_queue(second), Err(Error::DeadlineNotReached));
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(HOUR_MS);
+            contract.leave_queue(second).unwrap();
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(1000)
+            );
+            assert_eq!(contract.get_queue_length(4), 0);
+        }
+
+        #[ink::test]
+        fn queue_skips_patients_owing_a_deposit() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 4, tier: 1 })).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_deposit_requirement(Some(DepositRequirement {
+                no_show_threshold: 1,
+                deposit: 200,
+            })).unwrap();
+            contract.set_on_call(true).unwrap();
+
+            // Django misses a session with Eve and then queues for her specialty.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let missed_id = contract.book_consultation(accounts.eve, 2 * HOUR_MS).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.check_in(missed_id).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(3 * HOUR_MS);
+            contract.report_patient_no_show(missed_id).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(150);
+            let owing_ticket = contract.join_queue(4).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(contract.pull_next_case(4), Err(Error::NoAssignableTicket));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(150);
+            contract.join_queue(4).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            let consultation_id = contract.pull_next_case(4).unwrap();
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!((consultation.patient, consultation.amount), (accounts.frank, 150));
+            assert_eq!(contract.get_queue_ticket(owing_ticket).unwrap().status, TicketStatus::Waiting);
+            assert_eq!(contract.get_queue_length(4), 1);
+        }
+
+        #[ink::test]
+        fn cancelled_slot_is_offered_to_first_waitlisted_patient() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            let slot_time = 72 * HOUR_MS;
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.eve, slot_time).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            contract.join_waitlist(accounts.eve).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.cancel_consultation(consultation_id).unwrap();
+            assert_eq!(contract.get_slot_offer(accounts.eve, slot_time).unwrap().patient, accounts.frank);
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            assert_eq!(contract.book_consultation(accounts.eve, slot_time), Err(Error::SlotNotAvailable));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(900);
+            let rebooked_id = contract.book_consultation(accounts.eve, slot_time).unwrap();
+            assert_eq!(contract.get_consultation(rebooked_id).unwrap().amount, 1000);
+            assert_eq!(contract.get_consultation(rebooked_id).unwrap().waitlist_deposit, 100);
+            assert!(contract.get_slot_offer(accounts.eve, slot_time).is_none());
+            assert!(contract.get_waitlist(accounts.eve).is_empty());
+
+            // A slot that has already passed is not worth offering to anyone.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            contract.join_waitlist(accounts.eve).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(slot_time + 1);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.cancel_consultation(rebooked_id).unwrap();
+            assert!(contract.get_slot_offer(accounts.eve, slot_time).is_none());
+            assert_eq!(contract.get_waitlist(accounts.eve).len(), 1);
+        }
+
+        #[ink::test]
+        fn bookings_must_fall_within_booking_horizon() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(10 * HOUR_MS);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(10);
+            assert_eq!(
+                contract.book_consultation(accounts.eve, 9 * HOUR_MS),
+                Err(Error::InvalidScheduleTime)
+            );
+            assert_eq!(
+                contract.book_consultation(accounts.eve, 10 * HOUR_MS + MAX_BOOKING_HORIZON + 1),
+                Err(Error::InvalidScheduleTime)
+            );
+
+            // Many small bookings don't lock the doctor out of new ones.
+            let first_id = contract.book_consultation(accounts.eve, 100 * HOUR_MS).unwrap();
+            for _ in 0..250 {
+                contract.book_consultation(accounts.eve, 100 * HOUR_MS).unwrap();
+            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            assert!(contract.book_consultation(accounts.eve, 50 * HOUR_MS).is_ok());
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                contract.propose_reschedule(first_id, 10 * HOUR_MS + MAX_BOOKING_HORIZON + 1),
+                Err(Error::InvalidScheduleTime)
+            );
+        }
+
+        #[ink::test]
+        fn slot_offers_follow_reschedules_and_bookings() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            let old_time = 72 * HOUR_MS;
+            let new_time = 96 * HOUR_MS;
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.eve, old_time).unwrap();
+
+            for (patient, deposit) in [(accounts.frank, 100), (accounts.charlie, 50), (accounts.alice, 0)] {
+                ink::env::test::set_caller::<ink::env::DefaultEnvironment>(patient);
+                ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(deposit);
+                contract.join_waitlist(accounts.eve).unwrap();
+            }
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.announce_slot(new_time).unwrap();
+            assert_eq!(contract.get_slot_offer(accounts.eve, new_time).unwrap().patient, accounts.frank);
+
+            // Frank holds the new slot, so the booking can't be moved onto it.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.propose_reschedule(consultation_id, new_time).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(contract.accept_reschedule(consultation_id), Err(Error::SlotNotAvailable));
+
+            // Once his offer lapses the move goes through, returns his deposit and
+            // frees the old slot for the next patient in line.
+            let frank_before =
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(3 * HOUR_MS);
+            contract.accept_reschedule(consultation_id).unwrap();
+            assert!(contract.get_slot_offer(accounts.eve, new_time).is_none());
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.frank),
+                Ok(frank_before + 100)
+            );
+            assert_eq!(contract.get_slot_offer(accounts.eve, old_time).unwrap().patient, accounts.charlie);
+            assert_eq!(contract.get_waitlist(accounts.eve).len(), 1);
+
+            // Booking over Charlie's lapsed offer clears it and returns the deposit.
+            let charlie_before =
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.charlie).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(6 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            contract.book_consultation(accounts.eve, old_time).unwrap();
+            assert!(contract.get_slot_offer(accounts.eve, old_time).is_none());
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.charlie),
+                Ok(charlie_before + 50)
+            );
+        }
+
+        #[ink::test]
+        fn overtime_is_charged_per_started_increment() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            let minute = 60 * 1000;
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_session_duration(30 * minute).unwrap();
+            contract.set_overtime_terms(Some(OvertimeTerms {
+                increment: 10 * minute,
+                rate_per_increment: 50,
+            })).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.eve, 0).unwrap();
+            assert_eq!(contract.get_consultation(consultation_id).unwrap().duration, 30 * minute);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(consultation_id).unwrap();
 
-            self.env().emit_event(ConsultationCompleted { consultation_id });
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(200);
+            contract.authorize_overtime(consultation_id).unwrap();
 
-            Ok(())
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(55 * minute);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.mark_completed(consultation_id, "QmNotes".to_string()).unwrap();
+
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!(consultation.started_at, Some(0));
+            assert_eq!(consultation.ended_at, Some(55 * minute));
+            assert_eq!(consultation.overtime_charged, 150);
+            assert_eq!(consultation.amount, 1150);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(50)
+            );
         }
 
-        #[ink(message)]
-        pub fn release_payment(&mut self, consultation_id: u64) -> Result<()> {
-            let mut consultation = self.consultations
-                .get(consultation_id)
-                .ok_or(Error::ConsultationNotFound)?;
+        #[ink::test]
+        fn consultations_are_indexed_by_patient_and_doctor() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
 
-            if consultation.status != ConsultationStatus::Completed {
-                return Err(Error::InvalidStatus);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let first_id = contract.book_consultation(accounts.eve, 0).unwrap();
+            let second_id = contract.book_consultation(accounts.eve, 48 * HOUR_MS).unwrap();
+            let third_id = contract.book_consultation(accounts.frank, 48 * HOUR_MS).unwrap();
 
-            let completed_at = consultation.completed_at.ok_or(Error::InvalidStatus)?;
-            let dispute_window = 24 * 60 * 60 * 1000; // 24 hours in milliseconds
-            let current_time = self.env().block_timestamp();
-            
-            let time_since_completion = current_time
-                .checked_sub(completed_at)
-                .ok_or(Error::TooEarlyToRelease)?;
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(first_id).unwrap();
 
-            if time_since_completion < dispute_window {
-                if self.env().caller() != self.owner {
-                    return Err(Error::TooEarlyToRelease);
-                }
-            }
+            let patient_ids: Vec<u64> = contract
+                .get_patient_consultations(accounts.django, None, 0, 10)
+                .iter()
+                .map(|c| c.id)
+                .collect();
+            assert_eq!(patient_ids, ink::prelude::vec![first_id, second_id, third_id]);
 
-            let fee_amount = self.calculate_fee(consultation.amount)?;
-            let doctor_amount = consultation.amount
-                .checked_sub(fee_amount)
-                .ok_or(Error::OverflowError)?;
+            let pending: Vec<u64> = contract
+                .get_patient_consultations(accounts.django, Some(ConsultationStatus::Pending), 1, 10)
+                .iter()
+                .map(|c| c.id)
+                .collect();
+            assert_eq!(pending, ink::prelude::vec![third_id]);
 
-            if self.env().transfer(consultation.doctor, doctor_amount).is_err() {
-                return Err(Error::TransferFailed);
-            }
+            assert_eq!(contract.get_doctor_consultations(accounts.eve, None, 0, 1).len(), 1);
+            assert_eq!(
+                contract.get_doctor_status_counts(accounts.eve),
+                ink::prelude::vec![
+                    (ConsultationStatus::InProgress, 1),
+                    (ConsultationStatus::Pending, 1),
+                ]
+            );
+        }
 
-            if fee_amount > 0 {
-                if self.env().transfer(self.platform_wallet, fee_amount).is_err() {
-                    return Err(Error::TransferFailed);
-                }
-            }
+        #[ink::test]
+        fn settlements_update_earnings_totals() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
 
-            consultation.status = ConsultationStatus::Released;
-            self.consultations.insert(consultation_id, &consultation);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let released_id = contract.book_consultation(accounts.eve, 0).unwrap();
+            let cancelled_id = contract.book_consultation(accounts.eve, 48 * HOUR_MS).unwrap();
+            contract.book_consultation(accounts.eve, 72 * HOUR_MS).unwrap();
+            contract.cancel_consultation(cancelled_id).unwrap();
 
-            self.env().emit_event(PaymentReleased {
-                consultation_id,
-                doctor: consultation.doctor,
-                amount: doctor_amount,
-            });
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(released_id).unwrap();
+            contract.mark_completed(released_id, "QmNotes".to_string()).unwrap();
 
-            Ok(())
-        }
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(25 * HOUR_MS);
+            contract.release_payment(released_id).unwrap();
 
-        #[ink(message)]
-        pub fn dispute_consultation(&mut self, consultation_id: u64) -> Result<()> {
-            let caller = self.env().caller();
-            let mut consultation = self.consultations
-                .get(consultation_id)
-                .ok_or(Error::ConsultationNotFound)?;
+            let expected = EarningsTotals {
+                locked: 1000,
+                released: 970,
+                refunded: 1000,
+                fees: 30,
+                keeper_rewards: 0,
+            };
+            assert_eq!(contract.get_doctor_totals(accounts.eve), expected);
+            assert_eq!(contract.get_platform_totals(), expected);
+            assert_eq!(
+                contract.get_doctor_daily_earnings(accounts.eve, 0, 2),
+                ink::prelude::vec![
+                    (0, DailyEarnings::default()),
+                    (1, DailyEarnings { released: 970, fees: 30 }),
+                ]
+            );
+        }
 
-            if consultation.patient != caller {
-                return Err(Error::Unauthorized);
-            }
+        #[ink::test]
+        fn totals_reconcile_across_payment_flows() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 4, tier: 1 })).unwrap();
 
-            if consultation.status != ConsultationStatus::Completed {
-                return Err(Error::InvalidStatus);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_on_call(true).unwrap();
+            contract.set_package_offer(4, Some(400)).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            let plan_id = contract.create_subscription_plan(100, 10 * HOUR_MS, "QmTerms".to_string()).unwrap();
 
-            let completed_at = consultation.completed_at.ok_or(Error::InvalidStatus)?;
-            let dispute_window = 24 * 60 * 60 * 1000;
-            let current_time = self.env().block_timestamp();
-            
-            let time_since_completion = current_time
-                .checked_sub(completed_at)
-                .ok_or(Error::DisputeWindowExpired)?;
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let conference_id = contract.book_case_conference(
+                accounts.django,
+                ink::prelude::vec![(accounts.eve, 6000), (accounts.frank, 4000)],
+                0,
+            ).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(500);
+            let answered_case = contract.submit_async_case(4, "QmCase".to_string()).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(300);
+            let withdrawn_case = contract.submit_async_case(4, "QmOther".to_string()).unwrap();
+            contract.refund_async_case(withdrawn_case).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(100);
+            let subscription_id = contract.subscribe(plan_id).unwrap();
+            contract.renew_subscription(subscription_id).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(400);
+            let package_id = contract.purchase_package(accounts.eve, 4).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            contract.book_with_package(package_id, 200 * HOUR_MS).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(200);
+            contract.join_queue(4).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(150);
+            let left_ticket = contract.join_queue(4).unwrap();
+            let deposited: Balance = 1000 + 500 + 300 + 2 * 100 + 400 + 200 + 150;
 
-            if time_since_completion > dispute_window {
-                return Err(Error::DisputeWindowExpired);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.confirm_conference_completion(conference_id).unwrap();
+            contract.claim_async_case(answered_case).unwrap();
+            contract.respond_async_case(answered_case, "QmAnswer".to_string()).unwrap();
+            contract.pull_next_case(4).unwrap();
 
-            consultation.status = ConsultationStatus::Disputed;
-            self.consultations.insert(consultation_id, &consultation);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(5 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.cancel_subscription(subscription_id).unwrap();
 
-            self.env().emit_event(ConsultationDisputed { consultation_id });
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(49 * HOUR_MS);
+            contract.settle_lapsed_conference(conference_id).unwrap();
+            contract.release_async_payment(answered_case).unwrap();
+            contract.cancel_package(package_id).unwrap();
+            contract.leave_queue(left_ticket).unwrap();
 
-            Ok(())
+            // Still in escrow: the package session and the consultation pulled
+            // from the queue.
+            let totals = contract.get_platform_totals();
+            assert_eq!(totals.locked, 100 + 200);
+            assert_eq!(contract.get_doctor_totals(accounts.eve).locked, 100 + 200);
+            assert_eq!(contract.get_doctor_totals(accounts.frank).locked, 0);
+            assert_eq!(totals.released + totals.refunded + totals.fees + totals.locked, deposited);
         }
 
-        #[ink(message)]
-        pub fn refund_consultation(&mut self, consultation_id: u64) -> Result<()> {
-            let caller = self.env().caller();
-            
-            if caller != self.owner {
-                return Err(Error::Unauthorized);
-            }
+        #[ink::test]
+        fn release_issues_verifiable_receipt() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 7, tier: 1 })).unwrap();
 
-            let mut consultation = self.consultations
-                .get(consultation_id)
-                .ok_or(Error::ConsultationNotFound)?;
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract
+                .book_consultation_for(accounts.django, accounts.eve, 0)
+                .unwrap();
+            assert!(contract.get_receipt(consultation_id).is_none());
 
-            if consultation.status != ConsultationStatus::Disputed {
-                return Err(Error::InvalidStatus);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(consultation_id).unwrap();
+            contract.mark_completed(consultation_id, "QmNotes".to_string()).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(25 * HOUR_MS);
+            contract.release_payment(consultation_id).unwrap();
 
-            if self.env().transfer(consultation.patient, consultation.amount).is_err() {
-                return Err(Error::TransferFailed);
-            }
+            let receipt = contract.get_receipt(consultation_id).unwrap();
+            assert_eq!(receipt.receipt_number, 1);
+            assert_eq!(receipt.payer, accounts.charlie);
+            assert_eq!((receipt.gross, receipt.fee, receipt.net), (1000, 30, 970));
+            assert_eq!(receipt.patient_paid, 0);
+            assert_eq!(receipt.specialty_code, Some(7));
+            assert_eq!(receipt.settled_at, 25 * HOUR_MS);
+            assert_eq!(contract.get_patient_receipts(accounts.django, 0, 10), ink::prelude::vec![receipt.clone()]);
+            assert_eq!(contract.get_doctor_receipts(accounts.eve, 0, 10).len(), 1);
 
-            consultation.status = ConsultationStatus::Refunded;
-            self.consultations.insert(consultation_id, &consultation);
+            let receipt_hash = contract.get_receipt_hash(consultation_id).unwrap();
+            assert!(contract.verify_receipt(receipt.clone(), receipt_hash));
 
-            self.env().emit_event(ConsultationRefunded {
-                consultation_id,
-                patient: consultation.patient,
-                amount: consultation.amount,
-            });
+            let mut forged = receipt.clone();
+            forged.gross = 2000;
+            assert!(!contract.verify_receipt(forged, receipt_hash));
 
-            Ok(())
+            let mut forged = receipt;
+            forged.patient_paid = 1000;
+            assert!(!contract.verify_receipt(forged, receipt_hash));
         }
 
-        #[ink(message)]
-        pub fn get_consultation(&self, consultation_id: u64) -> Option<Consultation> {
-            self.consultations.get(consultation_id)
-        }
+        #[ink::test]
+        fn bond_is_slashed_on_no_show_and_withdrawn_after_cooldown() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.update_bond_settings(300, 7 * DAY_MS).unwrap();
 
-        #[ink(message)]
-        pub fn get_platform_fee_percent(&self) -> u8 {
-            self.platform_fee_percent
-        }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            contract.deposit_bond().unwrap();
 
-        fn calculate_fee(&self, amount: Balance) -> Result<Balance> {
-            let fee_percent = self.platform_fee_percent as u128;
-            let fee = amount
-                .checked_mul(fee_percent)
-                .ok_or(Error::OverflowError)?
-                .checked_div(100)
-                .ok_or(Error::OverflowError)?;
-            Ok(fee)
-        }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            let consultation_id = contract.book_consultation(accounts.eve, 2 * HOUR_MS).unwrap();
 
-        #[ink(message)]
-        pub fn update_platform_fee(&mut self, new_fee_percent: u8) -> Result<()> {
-            if self.env().caller() != self.owner {
-                return Err(Error::Unauthorized);
-            }
-            self.platform_fee_percent = new_fee_percent;
-            Ok(())
-        }
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2 * HOUR_MS);
+            contract.check_in(consultation_id).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(3 * HOUR_MS);
+            contract.report_no_show(consultation_id).unwrap();
 
-        #[ink(message)]
-        pub fn cancel_consultation(&mut self, consultation_id: u64) -> Result<()> {
-            let caller = self.env().caller();
-            let mut consultation = self.consultations
-                .get(consultation_id)
-                .ok_or(Error::ConsultationNotFound)?;
+            assert_eq!(contract.get_doctor_bond(accounts.eve), 700);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(1300)
+            );
 
-            if consultation.patient != caller && consultation.doctor != caller {
-                return Err(Error::Unauthorized);
-            }
+            let open_id = contract.book_consultation(accounts.eve, 10 * DAY_MS).unwrap();
 
-            if consultation.status != ConsultationStatus::Pending {
-                return Err(Error::CancellationNotAllowed);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.request_bond_withdrawal(700).unwrap();
+            assert_eq!(contract.withdraw_bond(), Err(Error::CooldownActive));
 
-            let current_time = self.env().block_timestamp();
-            let time_until_consultation = consultation.scheduled_time
-                .checked_sub(current_time)
-                .unwrap_or(0);
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(3 * HOUR_MS + 7 * DAY_MS);
+            assert_eq!(contract.withdraw_bond(), Err(Error::OpenConsultations));
 
-            let refund_amount = if time_until_consultation > 24 * 60 * 60 * 1000 {
-                consultation.amount
-            } else {
-                consultation.amount
-                    .checked_div(2)
-                    .ok_or(Error::OverflowError)?
-            };
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.cancel_consultation(open_id).unwrap();
 
-            if self.env().transfer(consultation.patient, refund_amount).is_err() {
-                return Err(Error::TransferFailed);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.withdraw_bond().unwrap();
+            assert_eq!(contract.get_doctor_bond(accounts.eve), 0);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.eve),
+                Ok(700)
+            );
+        }
 
-            if refund_amount < consultation.amount {
-                let doctor_compensation = consultation.amount
-                    .checked_sub(refund_amount)
-                    .ok_or(Error::OverflowError)?;
-                if self.env().transfer(consultation.doctor, doctor_compensation).is_err() {
-                    return Err(Error::TransferFailed);
-                }
-            }
+        #[ink::test]
+        fn conferences_and_async_cases_hold_back_bond_withdrawal() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.update_bond_settings(300, 7 * DAY_MS).unwrap();
+            contract.set_doctor_class(accounts.eve, Some(DoctorClass { specialty_code: 4, tier: 1 })).unwrap();
 
-            consultation.status = ConsultationStatus::Cancelled;
-            self.consultations.insert(consultation_id, &consultation);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            contract.deposit_bond().unwrap();
 
-            self.env().emit_event(ConsultationCancelled {
-                consultation_id,
-                cancelled_by: caller,
-                refund_amount,
-            });
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            let conference_id = contract.book_case_conference(
+                accounts.django,
+                ink::prelude::vec![(accounts.eve, 5000), (accounts.frank, 5000)],
+                10 * DAY_MS,
+            ).unwrap();
+            let case_id = contract.submit_async_case(4, "QmCase".to_string()).unwrap();
 
-            Ok(())
-        }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.claim_async_case(case_id).unwrap();
+            contract.request_bond_withdrawal(1000).unwrap();
 
-        #[ink(message)]
-        pub fn report_no_show(&mut self, consultation_id: u64) -> Result<()> {
-            let caller = self.env().caller();
-            let mut consultation = self.consultations
-                .get(consultation_id)
-                .ok_or(Error::ConsultationNotFound)?;
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(7 * DAY_MS);
+            assert_eq!(contract.withdraw_bond(), Err(Error::OpenConsultations));
 
-            if consultation.patient != caller {
-                return Err(Error::Unauthorized);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            contract.cancel_case_conference(conference_id).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(contract.withdraw_bond(), Err(Error::OpenConsultations));
 
-            let current_time = self.env().block_timestamp();
-            if current_time < consultation.scheduled_time {
-                return Err(Error::TooEarlyToRelease);
-            }
+            // The claimed case missed its response deadline long ago.
+            contract.refund_async_case(case_id).unwrap();
+            contract.withdraw_bond().unwrap();
+            assert_eq!(contract.get_doctor_bond(accounts.eve), 0);
+        }
 
-            if consultation.status != ConsultationStatus::Pending 
-                && consultation.status != ConsultationStatus::InProgress {
-                return Err(Error::InvalidStatus);
-            }
+        #[ink::test]
+        fn repeat_no_show_patients_pay_reliability_deposit() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
 
-            if self.env().transfer(consultation.patient, consultation.amount).is_err() {
-                return Err(Error::TransferFailed);
-            }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_deposit_requirement(Some(DepositRequirement {
+                no_show_threshold: 1,
+                deposit: 200,
+            })).unwrap();
 
-            consultation.status = ConsultationStatus::NoShow;
-            self.consultations.insert(consultation_id, &consultation);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let missed_id = contract.book_consultation(accounts.eve, 2 * HOUR_MS).unwrap();
 
-            self.env().emit_event(NoShowReported {
-                consultation_id,
-                doctor: consultation.doctor,
-            });
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.check_in(missed_id).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(3 * HOUR_MS);
+            contract.report_patient_no_show(missed_id).unwrap();
+            assert_eq!(contract.get_required_deposit(accounts.eve, accounts.django), 200);
 
-            Ok(())
-        }
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(200);
+            assert_eq!(
+                contract.book_consultation(accounts.eve, 4 * HOUR_MS),
+                Err(Error::DepositRequired)
+            );
 
-        #[ink(message, payable)]
-        pub fn book_verified_consultation(
-            &mut self,
-            doctor: AccountId,
-            scheduled_time: u64,
-        ) -> Result<u64> {
-            self.book_consultation(doctor, scheduled_time)
-        }
-    }
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1200);
+            let consultation_id = contract.book_consultation(accounts.eve, 4 * HOUR_MS).unwrap();
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!((consultation.amount, consultation.reliability_deposit), (1000, 200));
 
-    #[cfg(test)]
-    mod tests {
-        use super::*;
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(4 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(consultation_id).unwrap();
+            contract.mark_completed(consultation_id, "QmNotes".to_string()).unwrap();
+
+            assert_eq!(
+                contract.get_patient_reliability(accounts.django),
+                PatientReliability {
+                    completed: 1,
+                    late_cancellations: 0,
+                    no_shows: 1,
+                }
+            );
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(700)
+            );
+        }
 
         #[ink::test]
-        fn new_works() {
+        fn package_bookings_take_reliability_deposit() {
             let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
-            let contract = ConsultationEscrow::new(accounts.bob, 3, accounts.alice);
-            assert_eq!(contract.get_platform_fee_percent(), 3);
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_deposit_requirement(Some(DepositRequirement {
+                no_show_threshold: 1,
+                deposit: 200,
+            })).unwrap();
+            contract.set_package_offer(2, Some(800)).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(800);
+            let package_id = contract.purchase_package(accounts.eve, 2).unwrap();
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(0);
+            let missed_id = contract.book_with_package(package_id, 2 * HOUR_MS).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.check_in(missed_id).unwrap();
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(3 * HOUR_MS);
+            contract.report_patient_no_show(missed_id).unwrap();
+
+            // Package credit cannot cover the deposit; it has to be paid in cash.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                contract.book_with_package(package_id, 4 * HOUR_MS),
+                Err(Error::DepositRequired)
+            );
+
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(200);
+            let consultation_id = contract.book_with_package(package_id, 4 * HOUR_MS).unwrap();
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!((consultation.amount, consultation.reliability_deposit), (400, 200));
+            assert_eq!(contract.get_doctor_totals(accounts.eve).locked, 400);
         }
 
         #[ink::test]
-        fn book_consultation_works() {
+        fn late_start_does_not_block_no_show_claim() {
             let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
-            let mut contract = ConsultationEscrow::new(accounts.bob, 3, accounts.alice);
-            
-            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
-            
-            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000000000000);
-            
-            let result = contract.book_consultation(accounts.charlie, 1234567890);
-            assert!(result.is_ok());
-            
-            let consultation_id = result.unwrap();
-            assert_eq!(consultation_id, 1);
-            
-            let consultation = contract.get_consultation(consultation_id);
-            assert!(consultation.is_some());
-            assert_eq!(consultation.unwrap().status, ConsultationStatus::Pending);
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_overtime_terms(Some(OvertimeTerms {
+                increment: 10 * 60 * 1000,
+                rate_per_increment: 50,
+            })).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let consultation_id = contract.book_consultation(accounts.eve, 2 * HOUR_MS).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(2 * HOUR_MS);
+            contract.check_in(consultation_id).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(3 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(consultation_id).unwrap();
+            contract.attest_session(consultation_id, SessionPhase::Start).unwrap();
+            assert_eq!(contract.get_consultation(consultation_id).unwrap().doctor_checked_in_at, None);
+
+            // An overtime allowance authorized for the session goes back too.
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(300);
+            contract.authorize_overtime(consultation_id).unwrap();
+            contract.report_no_show(consultation_id).unwrap();
+
+            let consultation = contract.get_consultation(consultation_id).unwrap();
+            assert_eq!(consultation.status, ConsultationStatus::NoShow);
+            assert_eq!(consultation.overtime_allowance, 0);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(1300)
+            );
         }
 
         #[ink::test]
-        fn complete_flow_works() {
+        fn batched_releases_respect_dispute_window() {
             let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
-            let mut contract = ConsultationEscrow::new(accounts.bob, 3, accounts.alice);
-            
+            let mut contract = ConsultationEscrow::new(accounts.bob, 300, AccountId::from([0x0; 32]));
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let matured_id = contract.book_consultation(accounts.eve, 0).unwrap();
+            let recent_id = contract.book_consultation(accounts.eve, 0).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.start_consultation(matured_id).unwrap();
+            contract.mark_completed(matured_id, "QmNotes".to_string()).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(20 * HOUR_MS);
+            contract.start_consultation(recent_id).unwrap();
+            contract.mark_completed(recent_id, "QmNotes".to_string()).unwrap();
+
+            // The owner can't use a batch to cut a patient's dispute window short.
             ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
-            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000000000000);
-            let consultation_id = contract.book_consultation(accounts.charlie, 1234567890).unwrap();
-            
-            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.charlie);
-            assert!(contract.start_consultation(consultation_id).is_ok());
-            
-            assert!(contract.mark_completed(consultation_id, "QmNotes123".to_string()).is_ok());
-            
-            let consultation = contract.get_consultation(consultation_id).unwrap();
-            assert_eq!(consultation.status, ConsultationStatus::Completed);
+            assert_eq!(
+                contract.release_batch(ink::prelude::vec![recent_id]).unwrap(),
+                ink::prelude::vec![(recent_id, Err(Error::TooEarlyToRelease))]
+            );
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(25 * HOUR_MS);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(
+                contract.release_matured(accounts.eve, 10),
+                Ok(ink::prelude::vec![(matured_id, Ok(970))])
+            );
+            assert_eq!(
+                contract.get_consultation(recent_id).unwrap().status,
+                ConsultationStatus::Completed
+            );
         }
 
         #[ink::test]
-        fn unauthorized_access_fails() {
+        fn lapsed_subscription_cannot_be_renewed() {
             let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
-            let mut contract = ConsultationEscrow::new(accounts.bob, 3, accounts.alice);
-            
-            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
-            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000000000000);
-            let consultation_id = contract.book_consultation(accounts.charlie, 1234567890).unwrap();
-            
-            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
-            let result = contract.start_consultation(consultation_id);
-            assert_eq!(result, Err(Error::Unauthorized));
+            let mut contract = ConsultationEscrow::new(accounts.bob, 0, AccountId::from([0x0; 32]));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            let plan_id = contract.create_subscription_plan(1000, 100, "QmTerms".to_string()).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1000);
+            let subscription_id = contract.subscribe(plan_id).unwrap();
+
+            ink::env::test::set_block_timestamp::<ink::env::DefaultEnvironment>(250);
+            assert_eq!(contract.renew_subscription(subscription_id), Err(Error::SubscriptionLapsed));
+            assert_eq!(contract.get_subscription(subscription_id).unwrap().prepaid_periods, 1);
         }
     }
 }
\ No newline at end of file
@@ -6,6 +6,8 @@ mod health_registry {
     use ink::prelude::vec::Vec;
     use ink::storage::Mapping;
 
+    const MAX_RANKED_DOCTORS: usize = 50;
+
     #[ink(storage)]
     pub struct HealthRegistry {
         owner: AccountId,
@@ -15,6 +17,7 @@ mod health_registry {
         doctor_availability: Mapping<AccountId, AvailabilitySchedule>,
         total_doctors: u32,
         total_patients: u32,
+        escrow_address: Option<AccountId>,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq)]
@@ -32,6 +35,7 @@ mod health_registry {
         pub completed_consultations: u32,
         pub cancelled_consultations: u32,
         pub no_show_count: u32,
+        pub bonded_amount: Balance,
     }
 
     #[derive(Debug, Clone, PartialEq, Eq)]
@@ -70,6 +74,7 @@ mod health_registry {
         pub rating: u8,
         pub completion_rate: u8,  
         pub verified: bool,
+        pub bonded_amount: Balance,
     }
 
     #[ink(event)]
@@ -131,6 +136,7 @@ mod health_registry {
                 doctor_availability: Mapping::default(),
                 total_doctors: 0,
                 total_patients: 0,
+                escrow_address: None,
             }
         }
 
@@ -161,6 +167,7 @@ mod health_registry {
                 completed_consultations: 0,
                 cancelled_consultations: 0,
                 no_show_count: 0,
+                bonded_amount: 0,
             };
 
             self.doctors.insert(caller, &profile);
@@ -329,9 +336,58 @@ mod health_registry {
                 rating: profile.rating,
                 completion_rate,
                 verified: profile.verified,
+                bonded_amount: profile.bonded_amount,
             })
         }
 
+        #[ink(message)]
+        pub fn set_escrow_address(&mut self, escrow_address: AccountId) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::NotOwner);
+            }
+            self.escrow_address = Some(escrow_address);
+            Ok(())
+        }
+
+        /// Mirrors the performance bond a doctor holds in the consultation
+        /// escrow so listings can rank bonded doctors higher.
+        #[ink(message)]
+        pub fn set_doctor_bond(&mut self, doctor: AccountId, amount: Balance) -> Result<()> {
+            if self.escrow_address != Some(self.env().caller()) {
+                return Err(Error::Unauthorized);
+            }
+
+            let mut profile = self.doctors.get(doctor).ok_or(Error::DoctorNotFound)?;
+            profile.bonded_amount = amount;
+            self.doctors.insert(doctor, &profile);
+
+            Ok(())
+        }
+
+        /// Orders the given doctors for a listing: verified doctors first, then
+        /// by bonded amount and rating, highest first. Unregistered accounts are
+        /// dropped and at most `MAX_RANKED_DOCTORS` candidates are considered.
+        #[ink(message)]
+        pub fn rank_doctors(&self, candidates: Vec<AccountId>) -> Vec<AccountId> {
+            let mut ranked: Vec<(AccountId, DoctorProfile)> = Vec::new();
+            for doctor in candidates.into_iter().take(MAX_RANKED_DOCTORS) {
+                if ranked.iter().any(|(listed, _)| *listed == doctor) {
+                    continue;
+                }
+                if let Some(profile) = self.doctors.get(doctor) {
+                    ranked.push((doctor, profile));
+                }
+            }
+
+            ranked.sort_by(|(_, a), (_, b)| {
+                b.verified
+                    .cmp(&a.verified)
+                    .then(b.bonded_amount.cmp(&a.bonded_amount))
+                    .then(b.rating.cmp(&a.rating))
+            });
+            ranked.into_iter().map(|(doctor, _)| doctor).collect()
+        }
+
         #[ink(message)]
         pub fn increment_completed(&mut self, doctor: AccountId) -> Result<()> {
             let mut profile = self.doctors.get(doctor).ok_or(Error::DoctorNotFound)?;
@@ -350,6 +406,10 @@ mod health_registry {
 
         #[ink(message)]
         pub fn increment_cancelled(&mut self, doctor: AccountId) -> Result<()> {
+            if self.escrow_address != Some(self.env().caller()) {
+                return Err(Error::Unauthorized);
+            }
+
             let mut profile = self.doctors.get(doctor).ok_or(Error::DoctorNotFound)?;
             
             profile.cancelled_consultations = profile.cancelled_consultations
@@ -362,6 +422,10 @@ mod health_registry {
 
         #[ink(message)]
         pub fn increment_no_show(&mut self, doctor: AccountId) -> Result<()> {
+            if self.escrow_address != Some(self.env().caller()) {
+                return Err(Error::Unauthorized);
+            }
+
             let mut profile = self.doctors.get(doctor).ok_or(Error::DoctorNotFound)?;
             
             profile.no_show_count = profile.no_show_count
@@ -496,6 +560,9 @@ mod health_registry {
 
             contract.increment_completed(accounts.alice).unwrap();
             contract.increment_completed(accounts.alice).unwrap();
+            assert_eq!(contract.increment_cancelled(accounts.alice), Err(Error::Unauthorized));
+            assert_eq!(contract.increment_no_show(accounts.alice), Err(Error::Unauthorized));
+            contract.set_escrow_address(accounts.alice).unwrap();
             contract.increment_cancelled(accounts.alice).unwrap();
 
             let stats = contract.get_doctor_stats(accounts.alice);
@@ -533,7 +600,63 @@ mod health_registry {
             assert!(result.is_ok());
 
             let updated_slots = contract.get_available_slots(accounts.alice);
-            assert_eq!(updated_slots[0].is_booked, true);
+            assert!(updated_slots[0].is_booked);
+        }
+
+        #[ink::test]
+        fn only_escrow_sets_doctor_bond() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = HealthRegistry::new();
+
+            contract.register_doctor(
+                "Dr. Bonded".to_string(),
+                "Neurology".to_string(),
+                "LIC555".to_string(),
+                "QmHash555".to_string(),
+                800000000000,
+            ).unwrap();
+            assert_eq!(contract.set_doctor_bond(accounts.alice, 5000), Err(Error::Unauthorized));
+
+            contract.set_escrow_address(accounts.bob).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.bob);
+            contract.set_doctor_bond(accounts.alice, 5000).unwrap();
+
+            let stats = contract.get_doctor_stats(accounts.alice).unwrap();
+            assert_eq!(stats.bonded_amount, 5000);
+        }
+
+        #[ink::test]
+        fn ranking_puts_verified_and_bonded_doctors_first() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = HealthRegistry::new();
+
+            for doctor in [accounts.bob, accounts.charlie, accounts.django] {
+                ink::env::test::set_caller::<ink::env::DefaultEnvironment>(doctor);
+                contract.register_doctor(
+                    "Dr. Ranked".to_string(),
+                    "Cardiology".to_string(),
+                    "LIC777".to_string(),
+                    "QmHash777".to_string(),
+                    1000,
+                ).unwrap();
+            }
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.verify_doctor(accounts.django).unwrap();
+            contract.set_escrow_address(accounts.eve).unwrap();
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.set_doctor_bond(accounts.charlie, 5000).unwrap();
+
+            assert_eq!(
+                contract.rank_doctors(ink::prelude::vec![
+                    accounts.bob,
+                    accounts.frank,
+                    accounts.charlie,
+                    accounts.django,
+                    accounts.bob,
+                ]),
+                ink::prelude::vec![accounts.django, accounts.charlie, accounts.bob]
+            );
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,645 @@
+#![cfg_attr(not(feature = "std"), no_std, no_main)]
+
+#[ink::contract]
+mod insurance_claims {
+    use ink::prelude::vec::Vec;
+    use ink::storage::Mapping;
+
+    #[ink(storage)]
+    pub struct InsuranceClaims {
+        owner: AccountId,
+        escrow_address: AccountId,
+        insurers: Mapping<AccountId, Insurer>,
+        claims: Mapping<u64, Claim>,
+        next_claim_id: u64,
+        consultation_claims: Mapping<u64, u64>,
+        patient_claims: Mapping<AccountId, Vec<u64>>,
+        insurer_claims: Mapping<AccountId, Vec<u64>>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct Insurer {
+        pub pool_balance: Balance,
+        pub total_funded: Balance,
+        pub total_paid: Balance,
+        pub active: bool,
+        pub registered_at: u64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub struct Claim {
+        pub id: u64,
+        pub patient: AccountId,
+        pub insurer: AccountId,
+        pub consultation_id: u64,
+        pub receipt_number: u64,
+        pub doctor: AccountId,
+        pub claimed_amount: Balance,
+        pub approved_amount: Balance,
+        pub status: ClaimStatus,
+        pub reason_hash: Option<Hash>,
+        pub filed_at: u64,
+        pub reviewed_at: Option<u64>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    #[cfg_attr(feature = "std", derive(ink::storage::traits::StorageLayout))]
+    pub enum ClaimStatus {
+        Pending,
+        Approved,
+        PartiallyApproved,
+        Denied,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum ClaimDecision {
+        Approve,
+        Partial(Balance),
+        Deny,
+    }
+
+    // Mirrors `consultation_escrow::Receipt`. The field order has to match the
+    // escrow's definition for the cross-contract result to decode.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub struct Receipt {
+        pub receipt_number: u64,
+        pub consultation_id: u64,
+        pub payer: AccountId,
+        pub patient: AccountId,
+        pub doctor: AccountId,
+        pub gross: Balance,
+        pub patient_paid: Balance,
+        pub fee: Balance,
+        pub net: Balance,
+        pub asset: PaymentAsset,
+        pub specialty_code: Option<u16>,
+        pub booked_at: u64,
+        pub scheduled_time: u64,
+        pub completed_at: Option<u64>,
+        pub settled_at: u64,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum PaymentAsset {
+        Native,
+    }
+
+    #[ink(event)]
+    pub struct InsurerRegistered {
+        #[ink(topic)]
+        insurer: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct PoolFunded {
+        #[ink(topic)]
+        insurer: AccountId,
+        amount: Balance,
+        pool_balance: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ClaimFiled {
+        #[ink(topic)]
+        claim_id: u64,
+        #[ink(topic)]
+        patient: AccountId,
+        #[ink(topic)]
+        insurer: AccountId,
+        consultation_id: u64,
+        claimed_amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct ClaimReviewed {
+        #[ink(topic)]
+        claim_id: u64,
+        status: ClaimStatus,
+        approved_amount: Balance,
+        reason_hash: Hash,
+    }
+
+    #[derive(Debug, PartialEq, Eq)]
+    #[ink::scale_derive(Encode, Decode, TypeInfo)]
+    pub enum Error {
+        Unauthorized,
+        InsurerNotFound,
+        InsurerInactive,
+        InsurerAlreadyRegistered,
+        ClaimNotFound,
+        InvalidStatus,
+        ReceiptNotFound,
+        AlreadyClaimed,
+        InvalidAmount,
+        InsufficientPool,
+        TransferFailed,
+        Overflow,
+    }
+
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    impl InsuranceClaims {
+        #[ink(constructor)]
+        pub fn new(escrow_address: AccountId) -> Self {
+            Self {
+                owner: Self::env().caller(),
+                escrow_address,
+                insurers: Mapping::default(),
+                claims: Mapping::default(),
+                next_claim_id: 1,
+                consultation_claims: Mapping::default(),
+                patient_claims: Mapping::default(),
+                insurer_claims: Mapping::default(),
+            }
+        }
+
+        #[ink(message)]
+        pub fn set_escrow_address(&mut self, escrow_address: AccountId) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+            self.escrow_address = escrow_address;
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn register_insurer(&mut self, insurer: AccountId) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+
+            if self.insurers.contains(insurer) {
+                return Err(Error::InsurerAlreadyRegistered);
+            }
+
+            self.insurers.insert(insurer, &Insurer {
+                pool_balance: 0,
+                total_funded: 0,
+                total_paid: 0,
+                active: true,
+                registered_at: self.env().block_timestamp(),
+            });
+
+            self.env().emit_event(InsurerRegistered { insurer });
+
+            Ok(())
+        }
+
+        /// Suspends or reinstates an insurer. A suspended insurer accepts no new
+        /// claims but can still review pending ones and withdraw its pool.
+        #[ink(message)]
+        pub fn set_insurer_active(&mut self, insurer: AccountId, active: bool) -> Result<()> {
+            if self.env().caller() != self.owner {
+                return Err(Error::Unauthorized);
+            }
+
+            let mut record = self.insurers.get(insurer).ok_or(Error::InsurerNotFound)?;
+            record.active = active;
+            self.insurers.insert(insurer, &record);
+
+            Ok(())
+        }
+
+        #[ink(message, payable)]
+        pub fn fund_pool(&mut self) -> Result<()> {
+            let caller = self.env().caller();
+            let amount = self.env().transferred_value();
+
+            let mut insurer = self.insurers.get(caller).ok_or(Error::InsurerNotFound)?;
+            if amount == 0 {
+                return Err(Error::InvalidAmount);
+            }
+
+            insurer.pool_balance = insurer.pool_balance
+                .checked_add(amount)
+                .ok_or(Error::Overflow)?;
+            insurer.total_funded = insurer.total_funded
+                .checked_add(amount)
+                .ok_or(Error::Overflow)?;
+            self.insurers.insert(caller, &insurer);
+
+            self.env().emit_event(PoolFunded {
+                insurer: caller,
+                amount,
+                pool_balance: insurer.pool_balance,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn withdraw_pool(&mut self, amount: Balance) -> Result<()> {
+            let caller = self.env().caller();
+            let mut insurer = self.insurers.get(caller).ok_or(Error::InsurerNotFound)?;
+
+            insurer.pool_balance = insurer.pool_balance
+                .checked_sub(amount)
+                .ok_or(Error::InsufficientPool)?;
+
+            if self.env().transfer(caller, amount).is_err() {
+                return Err(Error::TransferFailed);
+            }
+
+            self.insurers.insert(caller, &insurer);
+            Ok(())
+        }
+
+        /// Files a claim against the receipt the escrow issued when the
+        /// consultation was released. A consultation has at most one live claim;
+        /// after a denial it can be filed again, with the same or another insurer.
+        #[ink(message)]
+        pub fn file_claim(
+            &mut self,
+            insurer: AccountId,
+            consultation_id: u64,
+            claimed_amount: Balance,
+        ) -> Result<u64> {
+            let caller = self.env().caller();
+
+            let insurer_record = self.insurers.get(insurer).ok_or(Error::InsurerNotFound)?;
+            if !insurer_record.active {
+                return Err(Error::InsurerInactive);
+            }
+
+            if self
+                .get_claim_for_consultation(consultation_id)
+                .is_some_and(|claim| claim.status != ClaimStatus::Denied)
+            {
+                return Err(Error::AlreadyClaimed);
+            }
+
+            let receipt = self.fetch_receipt(consultation_id)?;
+            if receipt.patient != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            // Only what the patient paid out of pocket is reimbursable, not
+            // subsidies or payments made on their behalf.
+            if claimed_amount == 0 || claimed_amount > receipt.patient_paid {
+                return Err(Error::InvalidAmount);
+            }
+
+            let claim_id = self.next_claim_id;
+            self.next_claim_id = self.next_claim_id
+                .checked_add(1)
+                .ok_or(Error::Overflow)?;
+
+            let claim = Claim {
+                id: claim_id,
+                patient: caller,
+                insurer,
+                consultation_id,
+                receipt_number: receipt.receipt_number,
+                doctor: receipt.doctor,
+                claimed_amount,
+                approved_amount: 0,
+                status: ClaimStatus::Pending,
+                reason_hash: None,
+                filed_at: self.env().block_timestamp(),
+                reviewed_at: None,
+            };
+            self.store_claim(&claim);
+
+            self.env().emit_event(ClaimFiled {
+                claim_id,
+                patient: caller,
+                insurer,
+                consultation_id,
+                claimed_amount,
+            });
+
+            Ok(claim_id)
+        }
+
+        #[ink(message)]
+        pub fn review_claim(
+            &mut self,
+            claim_id: u64,
+            decision: ClaimDecision,
+            reason_hash: Hash,
+        ) -> Result<()> {
+            let caller = self.env().caller();
+            let mut claim = self.claims.get(claim_id).ok_or(Error::ClaimNotFound)?;
+
+            if claim.insurer != caller {
+                return Err(Error::Unauthorized);
+            }
+
+            if claim.status != ClaimStatus::Pending {
+                return Err(Error::InvalidStatus);
+            }
+
+            let (status, approved_amount) = match decision {
+                ClaimDecision::Approve => (ClaimStatus::Approved, claim.claimed_amount),
+                ClaimDecision::Partial(amount) => {
+                    if amount == 0 || amount >= claim.claimed_amount {
+                        return Err(Error::InvalidAmount);
+                    }
+                    (ClaimStatus::PartiallyApproved, amount)
+                }
+                ClaimDecision::Deny => (ClaimStatus::Denied, 0),
+            };
+
+            if approved_amount > 0 {
+                let mut insurer = self.insurers.get(caller).ok_or(Error::InsurerNotFound)?;
+                insurer.pool_balance = insurer.pool_balance
+                    .checked_sub(approved_amount)
+                    .ok_or(Error::InsufficientPool)?;
+                insurer.total_paid = insurer.total_paid
+                    .checked_add(approved_amount)
+                    .ok_or(Error::Overflow)?;
+
+                if self.env().transfer(claim.patient, approved_amount).is_err() {
+                    return Err(Error::TransferFailed);
+                }
+
+                self.insurers.insert(caller, &insurer);
+            }
+
+            claim.status = status;
+            claim.approved_amount = approved_amount;
+            claim.reason_hash = Some(reason_hash);
+            claim.reviewed_at = Some(self.env().block_timestamp());
+            self.claims.insert(claim_id, &claim);
+
+            self.env().emit_event(ClaimReviewed {
+                claim_id,
+                status,
+                approved_amount,
+                reason_hash,
+            });
+
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_claim(&self, claim_id: u64) -> Option<Claim> {
+            self.claims.get(claim_id)
+        }
+
+        #[ink(message)]
+        pub fn get_claim_for_consultation(&self, consultation_id: u64) -> Option<Claim> {
+            self.consultation_claims
+                .get(consultation_id)
+                .and_then(|claim_id| self.claims.get(claim_id))
+        }
+
+        #[ink(message)]
+        pub fn get_patient_claims(&self, patient: AccountId) -> Vec<Claim> {
+            self.patient_claims
+                .get(patient)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|claim_id| self.claims.get(claim_id))
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn get_insurer_claims(&self, insurer: AccountId) -> Vec<Claim> {
+            self.insurer_claims
+                .get(insurer)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|claim_id| self.claims.get(claim_id))
+                .collect()
+        }
+
+        #[ink(message)]
+        pub fn get_insurer(&self, insurer: AccountId) -> Option<Insurer> {
+            self.insurers.get(insurer)
+        }
+
+        fn store_claim(&mut self, claim: &Claim) {
+            self.claims.insert(claim.id, claim);
+            self.consultation_claims.insert(claim.consultation_id, &claim.id);
+
+            let mut patient_ids = self.patient_claims.get(claim.patient).unwrap_or_default();
+            patient_ids.push(claim.id);
+            self.patient_claims.insert(claim.patient, &patient_ids);
+
+            let mut insurer_ids = self.insurer_claims.get(claim.insurer).unwrap_or_default();
+            insurer_ids.push(claim.id);
+            self.insurer_claims.insert(claim.insurer, &insurer_ids);
+        }
+
+        /// Looks up the settlement receipt in the consultation escrow. The escrow
+        /// only issues receipts for released consultations.
+        fn fetch_receipt(&self, consultation_id: u64) -> Result<Receipt> {
+            match self.query_receipt(consultation_id) {
+                Some(receipt) if receipt.consultation_id == consultation_id => Ok(receipt),
+                _ => Err(Error::ReceiptNotFound),
+            }
+        }
+
+        #[cfg(not(test))]
+        fn query_receipt(&self, consultation_id: u64) -> Option<Receipt> {
+            use ink::env::call::{build_call, ExecutionInput, Selector};
+            use ink::env::DefaultEnvironment;
+
+            build_call::<DefaultEnvironment>()
+                .call(self.escrow_address)
+                .exec_input(
+                    ExecutionInput::new(Selector::new(ink::selector_bytes!("get_receipt")))
+                        .push_arg(consultation_id)
+                )
+                .returns::<Option<Receipt>>()
+                .try_invoke()
+                .ok()
+                .and_then(|result| result.ok())
+                .flatten()
+        }
+
+        // The off-chain test environment cannot dispatch cross-contract calls,
+        // so unit tests read receipts from a stand-in for the escrow.
+        #[cfg(test)]
+        fn query_receipt(&self, consultation_id: u64) -> Option<Receipt> {
+            tests::ESCROW_RECEIPTS.with(|receipts| {
+                receipts
+                    .borrow()
+                    .iter()
+                    .find(|receipt| receipt.consultation_id == consultation_id)
+                    .cloned()
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::cell::RefCell;
+
+        std::thread_local! {
+            pub(super) static ESCROW_RECEIPTS: RefCell<Vec<Receipt>> = const { RefCell::new(Vec::new()) };
+        }
+
+        /// Has the stand-in escrow hold a receipt for a released consultation.
+        fn issue_receipt(consultation_id: u64, patient: AccountId, gross: Balance, patient_paid: Balance) {
+            ESCROW_RECEIPTS.with(|receipts| {
+                receipts.borrow_mut().push(Receipt {
+                    receipt_number: consultation_id,
+                    consultation_id,
+                    payer: patient,
+                    patient,
+                    doctor: AccountId::from([0x9; 32]),
+                    gross,
+                    patient_paid,
+                    fee: 0,
+                    net: gross,
+                    asset: PaymentAsset::Native,
+                    specialty_code: None,
+                    booked_at: 0,
+                    scheduled_time: 0,
+                    completed_at: Some(0),
+                    settled_at: 0,
+                });
+            });
+        }
+
+        #[ink::test]
+        fn new_works() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let contract = InsuranceClaims::new(accounts.bob);
+            assert_eq!(contract.owner, accounts.alice);
+            assert_eq!(contract.escrow_address, accounts.bob);
+        }
+
+        #[ink::test]
+        fn only_owner_registers_insurers() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = InsuranceClaims::new(accounts.bob);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(contract.register_insurer(accounts.eve), Err(Error::Unauthorized));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            assert!(contract.register_insurer(accounts.eve).is_ok());
+            assert_eq!(contract.register_insurer(accounts.eve), Err(Error::InsurerAlreadyRegistered));
+            assert!(contract.get_insurer(accounts.eve).unwrap().active);
+        }
+
+        #[ink::test]
+        fn claims_require_active_insurer() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = InsuranceClaims::new(accounts.bob);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.file_claim(accounts.eve, 1, 500), Err(Error::InsurerNotFound));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.alice);
+            contract.register_insurer(accounts.eve).unwrap();
+            contract.set_insurer_active(accounts.eve, false).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.file_claim(accounts.eve, 1, 500), Err(Error::InsurerInactive));
+        }
+
+        #[ink::test]
+        fn reviewed_claims_pay_patient_from_insurer_pool() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = InsuranceClaims::new(accounts.bob);
+            let contract_id = ink::env::test::callee::<ink::env::DefaultEnvironment>();
+            ink::env::test::set_account_balance::<ink::env::DefaultEnvironment>(contract_id, 1_000_000);
+            contract.register_insurer(accounts.eve).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            ink::env::test::set_value_transferred::<ink::env::DefaultEnvironment>(1500);
+            contract.fund_pool().unwrap();
+
+            issue_receipt(1, accounts.django, 1000, 1000);
+            issue_receipt(2, accounts.django, 1000, 1000);
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            let partial_id = contract.file_claim(accounts.eve, 1, 1000).unwrap();
+            let denied_id = contract.file_claim(accounts.eve, 2, 1000).unwrap();
+            let reason = Hash::from([0x1; 32]);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(
+                contract.review_claim(partial_id, ClaimDecision::Approve, reason),
+                Err(Error::Unauthorized)
+            );
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            assert_eq!(
+                contract.review_claim(partial_id, ClaimDecision::Partial(1000), reason),
+                Err(Error::InvalidAmount)
+            );
+            contract.review_claim(partial_id, ClaimDecision::Partial(600), reason).unwrap();
+            contract.review_claim(denied_id, ClaimDecision::Deny, reason).unwrap();
+            assert_eq!(
+                contract.review_claim(denied_id, ClaimDecision::Approve, reason),
+                Err(Error::InvalidStatus)
+            );
+
+            let claim = contract.get_claim(partial_id).unwrap();
+            assert_eq!(claim.status, ClaimStatus::PartiallyApproved);
+            assert_eq!(claim.approved_amount, 600);
+            assert_eq!(claim.reason_hash, Some(reason));
+            assert_eq!(contract.get_claim(denied_id).unwrap().status, ClaimStatus::Denied);
+
+            let insurer = contract.get_insurer(accounts.eve).unwrap();
+            assert_eq!(insurer.pool_balance, 900);
+            assert_eq!(insurer.total_paid, 600);
+            assert_eq!(
+                ink::env::test::get_account_balance::<ink::env::DefaultEnvironment>(accounts.django),
+                Ok(600)
+            );
+            assert_eq!(contract.get_patient_claims(accounts.django).len(), 2);
+        }
+
+        #[ink::test]
+        fn claims_are_capped_at_out_of_pocket_amount() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = InsuranceClaims::new(accounts.bob);
+            contract.register_insurer(accounts.eve).unwrap();
+            // A subsidy covered 400 of the 1000 booking.
+            issue_receipt(7, accounts.django, 1000, 600);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            assert_eq!(contract.file_claim(accounts.eve, 8, 100), Err(Error::ReceiptNotFound));
+            assert_eq!(contract.file_claim(accounts.eve, 7, 0), Err(Error::InvalidAmount));
+            assert_eq!(contract.file_claim(accounts.eve, 7, 601), Err(Error::InvalidAmount));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.frank);
+            assert_eq!(contract.file_claim(accounts.eve, 7, 600), Err(Error::Unauthorized));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            let claim_id = contract.file_claim(accounts.eve, 7, 600).unwrap();
+            let claim = contract.get_claim(claim_id).unwrap();
+            assert_eq!((claim.claimed_amount, claim.receipt_number), (600, 7));
+            assert_eq!(contract.get_claim_for_consultation(7), Some(claim));
+            assert_eq!(contract.file_claim(accounts.eve, 7, 600), Err(Error::AlreadyClaimed));
+        }
+
+        #[ink::test]
+        fn denied_claim_can_be_refiled_with_another_insurer() {
+            let accounts = ink::env::test::default_accounts::<ink::env::DefaultEnvironment>();
+            let mut contract = InsuranceClaims::new(accounts.bob);
+            contract.register_insurer(accounts.eve).unwrap();
+            contract.register_insurer(accounts.frank).unwrap();
+            issue_receipt(3, accounts.django, 500, 500);
+            let reason = Hash::from([0x2; 32]);
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            let denied_id = contract.file_claim(accounts.eve, 3, 500).unwrap();
+            assert_eq!(contract.file_claim(accounts.frank, 3, 500), Err(Error::AlreadyClaimed));
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.eve);
+            contract.review_claim(denied_id, ClaimDecision::Deny, reason).unwrap();
+
+            ink::env::test::set_caller::<ink::env::DefaultEnvironment>(accounts.django);
+            let refiled_id = contract.file_claim(accounts.frank, 3, 500).unwrap();
+            assert_ne!(refiled_id, denied_id);
+            assert_eq!(contract.get_claim_for_consultation(3).unwrap().insurer, accounts.frank);
+            assert_eq!(contract.get_patient_claims(accounts.django).len(), 2);
+            assert_eq!(contract.file_claim(accounts.eve, 3, 500), Err(Error::AlreadyClaimed));
+        }
+    }
+}